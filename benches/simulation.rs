@@ -0,0 +1,147 @@
+use criterion::{ criterion_group, criterion_main, BenchmarkId, Criterion, Throughput, };
+
+use drone_target::{ physics, drone, beam, ToExile };
+
+use rapier3d::{
+    dynamics::{ CCDSolver, JointSet, RigidBodySet, },
+    geometry::{ BroadPhase, NarrowPhase, ColliderSet, },
+    pipeline::QueryPipeline,
+};
+
+use dotrix::{
+    Transform,
+    services::{ World, },
+    math::{ Point3, },
+};
+
+// simulated frame time
+const DT: f32 = 1.0 / 60.0;
+
+// Headless game world with the physics services
+struct Simulation {
+    world: World,
+    pipeline: physics::Pipeline,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    joints: JointSet,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    ccd_solver: CCDSolver,
+    query_pipeline: QueryPipeline,
+    to_exile: ToExile,
+}
+
+impl Simulation {
+    fn new(drones: usize, beams: usize) -> Self {
+        let mut simulation = Self {
+            world: World::new(),
+            pipeline: physics::Pipeline::default(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            joints: JointSet::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            ccd_solver: CCDSolver::new(),
+            query_pipeline: QueryPipeline::new(),
+            to_exile: ToExile::default(),
+        };
+
+        for i in 0..beams {
+            let position = Point3::new(i as f32 * 300.0, 0.0, 0.0);
+            let body = beam::insert_body(
+                &mut simulation.bodies, &mut simulation.colliders, None, position
+            );
+
+            simulation.world.spawn(Some((body, beam::Stats::default())));
+        }
+
+        // place drones on a grid around the beams
+        let side = (drones as f32).cbrt().ceil() as usize;
+
+        for i in 0..drones {
+            let position = Point3::new(
+                (i % side) as f32 * 4.0 - side as f32 * 2.0,
+                ((i / side) % side) as f32 * 4.0 - side as f32 * 2.0,
+                (i / side / side) as f32 * 4.0 - side as f32 * 2.0 + 10.0,
+            );
+            let body = drone::insert_body(
+                &mut simulation.bodies, &mut simulation.colliders, None, position
+            );
+
+            simulation.world.spawn(Some((
+                Transform::default(),
+                body,
+                drone::Stats::default(),
+                physics::Forces::default(),
+            )));
+        }
+
+        // build the broad phase and the query pipeline
+        simulation.step();
+
+        simulation
+    }
+
+    fn step(&mut self) {
+        self.pipeline.run(
+            DT,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.joints,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.ccd_solver,
+            &mut self.query_pipeline,
+        );
+    }
+
+    fn gravity(&mut self) {
+        beam::apply_gravity(
+            &self.world, &mut self.bodies, &self.colliders, &self.query_pipeline
+        );
+    }
+
+    fn control(&mut self) {
+        drone::update(&self.world, &mut self.bodies, false, DT, &mut self.to_exile);
+        // drones are not despawned during the benchmark
+        self.to_exile.entity_list.clear();
+    }
+
+    fn tick(&mut self) {
+        self.step();
+        self.control();
+        self.gravity();
+    }
+}
+
+const SIZES: [(usize, usize); 4] = [(100, 1), (1000, 1), (1000, 8), (5000, 8)];
+
+fn bench_systems(c: &mut Criterion) {
+    let mut group = c.benchmark_group("systems");
+    // every iteration is a single tick, so elements per second are ticks
+    group.throughput(Throughput::Elements(1));
+    group.sample_size(20);
+
+    for &(drones, beams) in SIZES.iter() {
+        let id = format!("{}x{}", drones, beams);
+        let mut simulation = Simulation::new(drones, beams);
+
+        group.bench_function(BenchmarkId::new("physics::step", &id), |b| {
+            b.iter(|| simulation.step())
+        });
+        group.bench_function(BenchmarkId::new("beam::gravity", &id), |b| {
+            b.iter(|| simulation.gravity())
+        });
+        group.bench_function(BenchmarkId::new("drone::control", &id), |b| {
+            b.iter(|| simulation.control())
+        });
+        group.bench_function(BenchmarkId::new("tick", &id), |b| {
+            b.iter(|| simulation.tick())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_systems);
+criterion_main!(benches);
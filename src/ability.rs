@@ -0,0 +1,404 @@
+use super::{ Action, drone, local, targeting, view };
+
+use rapier3d::{
+    dynamics::{ RigidBodySet, RigidBodyHandle, },
+    na::{ Vector3, },
+};
+
+use dotrix::{
+    Frame,
+    services::{ World, Input, Camera, },
+    overlay::Overlay,
+    ecs::{ Mut, Const, Entity, },
+};
+
+use dotrix::egui::{
+    self,
+    Egui,
+};
+
+const DASH_IMPULSE: f32 = 15.0;
+const EMP_RADIUS:   f32 = 30.0;
+const EMP_DRAIN:    f32 = 40.0;
+const TRACTOR_RANGE: f32 = 60.0;
+const TRACTOR_FORCE: f32 = 3.0;
+// portion of the damage the shield absorbs
+pub const SHIELD_ABSORB: f32 = 0.75;
+const BUBBLE_RADIUS: f32 = 1.8;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Kind {
+    // absorbs the damage while active
+    Shield,
+    // instant impulse forward
+    Dash,
+    // drains the charge of drones around
+    Emp,
+    // pulls the locked target
+    Tractor,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Shield  => "SHIELD",
+            Kind::Dash    => "DASH",
+            Kind::Emp     => "EMP",
+            Kind::Tractor => "TRACTOR",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ability {
+    pub kind: Kind,
+    // charge spent on activation
+    pub cost: f32,
+    // seconds after activation before the ability is ready again
+    pub cooldown: f32,
+    // seconds the ability stays active
+    pub duration: f32,
+    // charge per second spent by a toggled ability while it's on
+    pub upkeep: f32,
+    pub ready_in: f32,
+    pub active_for: f32,
+}
+
+impl Ability {
+    fn new(kind: Kind, cost: f32, cooldown: f32, duration: f32) -> Self {
+        Self {
+            kind,
+            cost,
+            cooldown,
+            duration,
+            upkeep: 0.0,
+            ready_in: 0.0,
+            active_for: 0.0,
+        }
+    }
+
+    // Ability that stays on until toggled off or out of charge, the cooldown
+    // starts when it is turned off
+    fn toggle(kind: Kind, cost: f32, cooldown: f32, upkeep: f32) -> Self {
+        Self {
+            upkeep,
+            duration: f32::INFINITY,
+            ..Self::new(kind, cost, cooldown, 0.0)
+        }
+    }
+
+    pub fn is_toggle(&self) -> bool {
+        self.upkeep > 0.0
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready_in <= 0.0
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active_for > 0.0
+    }
+
+    // Starts the ability paying its cost, returns false if it is not ready
+    // or the charge is too low. A toggled ability is turned off if it's on.
+    pub fn activate(&mut self, stats: &mut drone::Stats) -> bool {
+        if self.is_toggle() && self.is_active() {
+            self.deactivate();
+            return true;
+        }
+
+        if !self.is_ready() || stats.charge < self.cost {
+            return false;
+        }
+
+        stats.charge = stats.charge - self.cost;
+        stats.strike_charge = stats.strike_charge.min(stats.charge);
+        self.active_for = self.duration;
+
+        if !self.is_toggle() {
+            self.ready_in = self.cooldown;
+        }
+
+        true
+    }
+
+    pub fn deactivate(&mut self) {
+        self.active_for = 0.0;
+        self.ready_in = self.cooldown;
+    }
+
+    // Spends the upkeep of a toggled ability, turning it off when the
+    // charge runs out
+    pub fn sustain(&mut self, stats: &mut drone::Stats, dt: f32) {
+        if !self.is_toggle() || !self.is_active() {
+            return;
+        }
+
+        let upkeep = self.upkeep * dt;
+
+        if stats.charge < upkeep {
+            self.deactivate();
+        } else {
+            stats.charge = stats.charge - upkeep;
+            stats.strike_charge = stats.strike_charge.min(stats.charge);
+        }
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        self.ready_in = (self.ready_in - dt).max(0.0);
+        self.active_for = (self.active_for - dt).max(0.0);
+    }
+}
+
+pub struct Abilities {
+    pub list: [Ability; 4],
+}
+
+impl Default for Abilities {
+    fn default() -> Self {
+        Self {
+            list: [
+                Ability::toggle(Kind::Shield, 5.0, 2.0, 4.0),
+                Ability::new(Kind::Dash,    10.0,  3.0, 0.0),
+                Ability::new(Kind::Emp,     30.0, 15.0, 0.0),
+                Ability::new(Kind::Tractor, 15.0, 10.0, 3.0),
+            ],
+        }
+    }
+}
+
+impl Abilities {
+    pub fn get(&self, kind: Kind) -> &Ability {
+        self.list.iter().find(|a| a.kind == kind).unwrap()
+    }
+
+    pub fn get_mut(&mut self, kind: Kind) -> &mut Ability {
+        self.list.iter_mut().find(|a| a.kind == kind).unwrap()
+    }
+}
+
+// action activating every ability
+pub fn action(kind: Kind) -> Action {
+    match kind {
+        Kind::Shield  => Action::Shield,
+        Kind::Dash    => Action::Dash,
+        Kind::Emp     => Action::Emp,
+        Kind::Tractor => Action::Tractor,
+    }
+}
+
+// control of the other local players activating every ability
+pub fn control(kind: Kind) -> local::Control {
+    match kind {
+        Kind::Shield  => local::Control::Shield,
+        Kind::Dash    => local::Control::Dash,
+        Kind::Emp     => local::Control::Emp,
+        Kind::Tractor => local::Control::Tractor,
+    }
+}
+
+pub fn update(
+    world: Const<World>,
+    mut bodies: Mut<RigidBodySet>,
+    input: Const<Input>,
+    frame: Const<Frame>,
+    rig: Const<view::Rig>,
+    lock_on: Const<targeting::LockOn>,
+) {
+    let dt = 1.0 / frame.fps();
+
+    // EMP pulses and tractor beams fired this frame: source entity and
+    // position, and the target locked by the source of the tractor beam
+    let mut pulses: Vec<(Entity, Vector3<f32>)> = Vec::new();
+    let mut tractors: Vec<(Entity, Vector3<f32>, Entity)> = Vec::new();
+
+    let query = world.query::<(
+        &Entity, &mut RigidBodyHandle, &mut drone::Stats, &mut Abilities
+    )>();
+
+    for (entity, rigid_body, stats, abilities) in query {
+        for ability in abilities.list.iter_mut() {
+            ability.tick(dt);
+            ability.sustain(stats, dt);
+        }
+
+        let body = bodies.get_mut(*rigid_body).unwrap();
+        let position = body.position().translation.vector;
+
+        if stats.is_player & (!stats.is_primary() | rig.controls_player()) {
+            for ability in abilities.list.iter_mut() {
+                let activated = input.is_action_activated(
+                    local::action(stats.slot, action(ability.kind), control(ability.kind))
+                );
+
+                if !activated {
+                    continue;
+                }
+
+                if !ability.activate(stats) {
+                    continue;
+                }
+
+                match ability.kind {
+                    Kind::Dash => {
+                        let (fwd, _) = drone::axes(&drone::rotation(body));
+                        body.apply_impulse(fwd * DASH_IMPULSE, true);
+                    },
+                    Kind::Emp => pulses.push((*entity, position)),
+                    _ => {},
+                }
+            }
+        }
+
+        stats.shield = if abilities.get(Kind::Shield).is_active() {
+            SHIELD_ABSORB
+        } else {
+            0.0
+        };
+
+        let target = lock_on.slots.get(stats.slot)
+            .and_then(|lock| lock.target)
+            .filter(|_| stats.is_player);
+
+        if let (true, Some(target)) = (abilities.get(Kind::Tractor).is_active(), target) {
+            tractors.push((*entity, position, target));
+        }
+    }
+
+    if pulses.is_empty() && tractors.is_empty() {
+        return;
+    }
+
+    let query = world.query::<( &Entity, &mut RigidBodyHandle, &mut drone::Stats )>();
+
+    for (entity, rigid_body, stats) in query {
+        let body = bodies.get_mut(*rigid_body).unwrap();
+        let position = body.position().translation.vector;
+
+        for (source, origin) in pulses.iter() {
+            if entity != source && (position - origin).norm() < EMP_RADIUS {
+                stats.charge = (stats.charge - EMP_DRAIN).max(0.0);
+                stats.strike_charge = stats.strike_charge.min(stats.charge);
+            }
+        }
+
+        // the tractor beam holds the target locked by its source
+        for (source, origin, target) in tractors.iter() {
+            let offset = origin - position;
+
+            if entity == target && entity != source
+                && offset.norm() < TRACTOR_RANGE && offset.norm() > 0.0
+            {
+                body.apply_force(offset.normalize() * TRACTOR_FORCE, true);
+            }
+        }
+    }
+}
+
+// Draws a bubble around every shielded drone
+pub fn bubbles(
+    world: Const<World>,
+    overlay: Const<Overlay>,
+    bodies: Const<RigidBodySet>,
+    camera: Const<Camera>,
+) {
+    let egui = overlay.get::<Egui>()
+        .expect("Renderer does not contain an Overlay instance");
+
+    let screen = egui.ctx.input().screen_rect();
+    let painter = egui.ctx.layer_painter(
+        egui::LayerId::new(egui::Order::Background, egui::Id::new("shield_bubbles"))
+    );
+
+    let query = world.query::<( &mut RigidBodyHandle, &mut drone::Stats )>();
+
+    for (rigid_body, stats) in query {
+        if stats.shield <= 0.0 {
+            continue;
+        }
+
+        let position = bodies.get(*rigid_body).unwrap().position().translation.vector;
+
+        let center = view::to_screen(&camera, position, screen);
+        let radius = view::screen_radius(&camera, position, BUBBLE_RADIUS, screen);
+
+        if let (Some(center), Some(radius)) = (center, radius) {
+            painter.circle(
+                center,
+                radius,
+                egui::Color32::from_rgba_unmultiplied(64, 160, 255, 48),
+                egui::Stroke::new(1.5, egui::Color32::from_rgba_unmultiplied(128, 200, 255, 160)),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn activation_costs_charge_and_starts_cooldown() {
+        let mut ability = Ability::new(Kind::Emp, 30.0, 15.0, 0.0);
+        let mut stats = drone::Stats { charge: 50.0, ..Default::default() };
+
+        assert!(ability.activate(&mut stats));
+        assert_eq!(stats.charge, 20.0);
+        assert!(!ability.is_ready());
+
+        // on cooldown
+        assert!(!ability.activate(&mut stats));
+
+        ability.tick(15.0);
+        assert!(ability.is_ready());
+
+        // not enough charge
+        assert!(!ability.activate(&mut stats));
+        assert_eq!(stats.charge, 20.0);
+    }
+
+    #[test]
+    fn toggled_ability_drains_charge_until_empty() {
+        let mut ability = Ability::toggle(Kind::Shield, 5.0, 2.0, 4.0);
+        let mut stats = drone::Stats { charge: 15.0, ..Default::default() };
+
+        assert!(ability.activate(&mut stats));
+        assert!(ability.is_active());
+        assert_eq!(stats.charge, 10.0);
+
+        ability.sustain(&mut stats, 2.0);
+        assert_eq!(stats.charge, 2.0);
+        assert!(ability.is_active());
+
+        // out of charge
+        ability.sustain(&mut stats, 1.0);
+        assert!(!ability.is_active());
+        assert!(!ability.is_ready());
+        assert_eq!(stats.charge, 2.0);
+    }
+
+    #[test]
+    fn toggled_ability_turns_off() {
+        let mut ability = Ability::toggle(Kind::Shield, 5.0, 2.0, 4.0);
+        let mut stats = drone::Stats { charge: 15.0, ..Default::default() };
+
+        ability.activate(&mut stats);
+        assert!(ability.activate(&mut stats));
+
+        assert!(!ability.is_active());
+        assert_eq!(ability.ready_in, 2.0);
+    }
+
+    #[test]
+    fn ability_stays_active_for_its_duration() {
+        let mut ability = Ability::new(Kind::Tractor, 0.0, 10.0, 4.0);
+        let mut stats = drone::Stats::default();
+
+        ability.activate(&mut stats);
+        ability.tick(3.0);
+        assert!(ability.is_active());
+
+        ability.tick(1.0);
+        assert!(!ability.is_active());
+    }
+}
@@ -0,0 +1,305 @@
+use super::{ beam, hull };
+
+use serde::{ Serialize, Deserialize, };
+
+use rapier3d::{
+    dynamics::{ RigidBodyBuilder, BodyStatus, RigidBodySet, RigidBodyHandle, },
+    geometry::{ ColliderSet, ColliderBuilder, },
+    na::{ Vector3, },
+};
+
+use dotrix::{
+    Transform,
+    Pipeline,
+    pbr:: { Model, Material, },
+    services::{ Assets, World, },
+    math::{ Point3, Vec3, },
+    ecs::{ Mut, Const, Entity, },
+};
+
+pub const DEFAULT_LEVEL: &str = "assets/levels/arena.ron";
+pub const LEVELS_DIR: &str = "assets/levels";
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Ball(f32),
+    Cuboid(f32, f32, f32),
+}
+
+impl Shape {
+    // scale of a unit sized model matching the shape
+    fn scale(&self) -> Vec3 {
+        match *self {
+            Shape::Ball(radius)    => Vec3::new(radius, radius, radius),
+            Shape::Cuboid(x, y, z) => Vec3::new(x, y, z),
+        }
+    }
+
+    fn collider(&self) -> ColliderBuilder {
+        match *self {
+            Shape::Ball(radius)    => ColliderBuilder::ball(radius),
+            Shape::Cuboid(x, y, z) => ColliderBuilder::cuboid(x, y, z),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Obstacle {
+    pub shape: Shape,
+    pub position: (f32, f32, f32),
+    // name of the imported glTF model, the obstacle is invisible without it
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    pub name: String,
+    // radius of the sphere drones can't leave
+    pub boundary: Option<f32>,
+    pub beams: Vec<(f32, f32, f32)>,
+    pub obstacles: Vec<Obstacle>,
+}
+
+impl Level {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path, e))?;
+
+        ron::from_str(&data)
+            .map_err(|e| format!("Could not parse {}: {}", path, e))
+    }
+}
+
+// Path and name of the levels in the directory, sorted by the path. Files
+// that don't parse are skipped.
+pub fn list(dir: &str) -> Vec<(String, String)> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e)      => {
+            println!("Could not read {}: {}", dir, e);
+            return Vec::new();
+        },
+    };
+
+    let mut levels: Vec<(String, String)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |extension| extension == "ron"))
+        .filter_map(|path| {
+            let path = path.to_string_lossy().into_owned();
+
+            match Level::load(&path) {
+                Ok(level) => Some((path, level.name)),
+                Err(e)    => {
+                    println!("{}", e);
+                    None
+                },
+            }
+        })
+        .collect();
+
+    levels.sort();
+    levels
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Self {
+            name: String::from("Empty space"),
+            boundary: None,
+            beams: vec![(0.0, 0.0, 0.0)],
+            obstacles: Vec::new(),
+        }
+    }
+}
+
+pub struct Arena {
+    pub boundary: Option<f32>,
+}
+
+// Marks the obstacles, removed with the level
+pub struct Scenery {}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Self {
+            boundary: None,
+        }
+    }
+}
+
+// Adds the obstacle rigid body and collider to the physics sets
+pub fn insert_body(
+    obstacle: &Obstacle,
+    bodies: &mut RigidBodySet,
+    colliders: &mut ColliderSet,
+) -> RigidBodyHandle {
+    let (x, y, z) = obstacle.position;
+
+    let rigid_body = RigidBodyBuilder::new(BodyStatus::Static)
+        .translation(x, y, z)
+        .build();
+
+    let body_handle = bodies.insert(rigid_body);
+
+    colliders.insert(obstacle.shape.collider().build(), body_handle, bodies);
+
+    body_handle
+}
+
+// Spawns beams and obstacles of the level
+pub fn spawn(
+    level: &Level,
+    arena: &mut Arena,
+    world: &mut World,
+    assets: &mut Assets,
+    bodies: &mut RigidBodySet,
+    colliders: &mut ColliderSet,
+    hulls: &hull::Hulls,
+) {
+    arena.boundary = level.boundary;
+
+    for position in level.beams.iter() {
+        beam::spawn(
+            world,
+            assets,
+            bodies,
+            colliders,
+            hulls,
+            Point3::new(position.0, position.1, position.2),
+        );
+    }
+
+    for obstacle in level.obstacles.iter() {
+        let (x, y, z) = obstacle.position;
+
+        let body_handle = insert_body(obstacle, bodies, colliders);
+
+        let transform = Transform {
+            translate: Vec3::new(x, y, z),
+            scale: obstacle.shape.scale(),
+            ..Default::default()
+        };
+
+        match &obstacle.model {
+            Some(model) => {
+                let texture = assets.register(&format!("{}::texture", model));
+                let mesh = assets.register(&format!("{}::mesh", model));
+
+                world.spawn(Some((
+                    Model::from(mesh),
+                    Material {
+                        texture,
+                        ..Default::default()
+                    },
+                    transform,
+                    body_handle,
+                    Scenery {},
+                    Pipeline::default(),
+                )));
+            },
+            None => {
+                world.spawn(Some((transform, body_handle, Scenery {})));
+            },
+        }
+    }
+}
+
+// Queues the beams and obstacles of the current level for removal
+pub fn clear(world: &World, to_exile: &mut Vec<Entity>) {
+    for (entity, _) in world.query::<( &Entity, &beam::Stats )>() {
+        to_exile.push(*entity);
+    }
+
+    for (entity, _) in world.query::<( &Entity, &Scenery )>() {
+        to_exile.push(*entity);
+    }
+}
+
+// Position and velocity of a body kept inside the boundary sphere
+pub fn confine(
+    position: Vector3<f32>,
+    velocity: Vector3<f32>,
+    radius: f32,
+) -> Option<(Vector3<f32>, Vector3<f32>)> {
+    let distance = position.norm();
+
+    if distance <= radius {
+        return None;
+    }
+
+    let normal = position / distance;
+    let outward = velocity.dot(&normal).max(0.0);
+
+    Some((normal * radius, velocity - normal * outward))
+}
+
+// Stops dynamic bodies at the arena boundary
+pub fn boundary(
+    arena: Const<Arena>,
+    mut bodies: Mut<RigidBodySet>,
+) {
+    if let Some(radius) = arena.boundary {
+        confine_bodies(&mut bodies, radius);
+    }
+}
+
+// Keeps dynamic bodies inside the sphere of `radius`
+pub fn confine_bodies(bodies: &mut RigidBodySet, radius: f32) {
+    for (_, body) in bodies.iter_mut() {
+        if !body.is_dynamic() {
+            continue;
+        }
+
+        let position = body.position().translation.vector;
+
+        if let Some((position, velocity)) = confine(position, *body.linvel(), radius) {
+            let mut isometry = *body.position();
+            isometry.translation.vector = position;
+
+            body.set_position(isometry, true);
+            body.set_linvel(velocity, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_inside_are_free() {
+        let position = Vector3::new(10.0, 0.0, 0.0);
+        let velocity = Vector3::new(100.0, 0.0, 0.0);
+
+        assert!(confine(position, velocity, 20.0).is_none());
+    }
+
+    #[test]
+    fn bodies_outside_are_pulled_back_and_stopped() {
+        let position = Vector3::new(0.0, 30.0, 0.0);
+        let velocity = Vector3::new(5.0, 10.0, 0.0);
+
+        let (position, velocity) = confine(position, velocity, 20.0).unwrap();
+
+        assert_eq!(position, Vector3::new(0.0, 20.0, 0.0));
+        // only the outward velocity is removed
+        assert_eq!(velocity, Vector3::new(5.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn default_level_parses() {
+        let level = Level::load(DEFAULT_LEVEL).unwrap();
+
+        assert!(!level.beams.is_empty());
+        assert!(level.boundary.is_some());
+    }
+
+    #[test]
+    fn shipped_levels_are_listed() {
+        let levels = list(LEVELS_DIR);
+
+        assert!(levels.len() >= 2);
+        assert!(levels.iter().any(|(path, _)| path.ends_with("arena.ron")));
+    }
+}
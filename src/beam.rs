@@ -1,7 +1,12 @@
+use super::{ hull, physics };
+
 use rapier3d::{
     dynamics::{ RigidBodyBuilder, BodyStatus, RigidBodySet, RigidBodyHandle },
-    geometry::{ ColliderSet, ColliderBuilder, },
-    na,
+    geometry::{
+        Ball, Collider, ColliderHandle, ColliderSet, InteractionGroups,
+    },
+    pipeline::QueryPipeline,
+    na::{ self, Vector3, },
 };
 
 use dotrix::{
@@ -10,15 +15,32 @@ use dotrix::{
     pbr:: { Model, Material, },
     services::{ Assets, World, },
     math::{ Point3, Vec3, },
-    ecs::{ Mut, },
+    ecs::{ Mut, Const, },
 };
 
+use std::collections::{ HashMap, HashSet, };
+
 // beam size
 const SCALE: f32 = 5.0;
+// model scale matching the beam size
+const MODEL_SCALE: f32 = 0.57 * SCALE;
+
+// How the gravity force changes with the distance to the beam
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Falloff {
+    // from the max force in the center to zero at the gravity radius
+    Linear,
+    // the max force at the beam surface, decreasing with squared distance
+    InverseSquare,
+    // the max force everywhere inside the gravity radius
+    Constant,
+}
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Stats {
     pub gravity_radius:    f32,
     pub gravity_max_force: f32,
+    pub falloff:           Falloff,
 
     pub radius_near:   f32,
     pub radius_medium: f32,
@@ -30,6 +52,7 @@ impl Default for Stats {
         Self {
             gravity_radius:    125.0,
             gravity_max_force:   2.5,
+            falloff:           Falloff::Linear,
 
             radius_near:    25.0,
             radius_medium:  75.0,
@@ -41,67 +64,131 @@ impl Default for Stats {
 pub fn gravity(
     world: Mut<World>,
     mut bodies: Mut<RigidBodySet>,
+    colliders: Const<ColliderSet>,
+    query_pipeline: Const<QueryPipeline>,
 ) {
+    apply_gravity(&world, &mut bodies, &colliders, &query_pipeline);
+}
+
+// Pulls dynamic bodies towards the beams
+pub fn apply_gravity(
+    world: &World,
+    bodies: &mut RigidBodySet,
+    colliders: &ColliderSet,
+    query_pipeline: &QueryPipeline,
+) {
+    let mut applied: HashMap<RigidBodyHandle, Vector3<f32>> = HashMap::new();
+
     // Query the beams
     let beams_query =
         world.query::<(&mut RigidBodyHandle, &mut Stats)>();
 
     for (beam_rigid_body, beam_stats) in beams_query {
 
-        let beam_body = bodies.get_mut(*beam_rigid_body).unwrap();
-        let beam_position = beam_body.position().translation;
+        let beam_body = bodies.get(*beam_rigid_body).unwrap();
+        let beam_position = *beam_body.position();
 
-        // Query all rigid bodies
-        let objects_query = world.query::<(&mut RigidBodyHandle, )>();
+        // find dynamic bodies in the gravity radius, except the beam itself
+        let mut affected: HashSet<RigidBodyHandle> = HashSet::new();
 
-        for (rigid_body, ) in objects_query {
+        let filter = |_: ColliderHandle, collider: &Collider| {
+            collider.parent() != *beam_rigid_body &&
+                bodies.get(collider.parent()).map_or(false, |body| body.is_dynamic())
+        };
 
-            let body = bodies.get_mut(*rigid_body).unwrap();
-            let position = body.position().translation;
+        query_pipeline.intersections_with_shape(
+            colliders,
+            &beam_position,
+            &Ball::new(beam_stats.gravity_radius),
+            InteractionGroups::all(),
+            Some(&filter),
+            |handle| {
+                // a body with several colliders is pulled once
+                affected.insert(colliders.get(handle).unwrap().parent());
+                true
+            },
+        );
 
-            let distance = na::distance(
-                &na::Point3::new(position.x, position.y, position.z,),
-                &na::Point3::new(
-                    beam_position.x, beam_position.y, beam_position.z)
-            );
-
-            if distance < beam_stats.gravity_radius {
-                let gravity_force = beam_stats.gravity_max_force *
-                    (1.0 - distance/beam_stats.gravity_radius);
+        for rigid_body in affected {
+            let body = bodies.get_mut(rigid_body).unwrap();
+            let position = body.position().translation;
 
-                let direction =
-                    (beam_position.vector - position.vector).normalize();
+            let force = force(&beam_position.translation.vector, &position.vector, beam_stats);
 
-                body.apply_force(direction * gravity_force, true);
+            if force != Vector3::new(0.0, 0.0, 0.0) {
+                body.apply_force(force, true);
+                *applied.entry(rigid_body).or_insert(Vector3::new(0.0, 0.0, 0.0)) += force;
             }
+        }
+    }
+
+    // record the total gravity of every body for the debug draw
+    let query = world.query::<( &mut RigidBodyHandle, &mut physics::Forces )>();
 
+    for (rigid_body, forces) in query {
+        forces.gravity = applied.get(rigid_body)
+            .copied()
+            .unwrap_or(Vector3::new(0.0, 0.0, 0.0));
+    }
+}
 
+// gravity force applied by a beam to a body at `position`
+pub fn force(
+    beam_position: &Vector3<f32>,
+    position: &Vector3<f32>,
+    stats: &Stats,
+) -> Vector3<f32> {
+    let offset = beam_position - position;
+    let distance = offset.norm();
 
-        }
+    // a body in the very center of the beam has no direction to be pulled in
+    if distance >= stats.gravity_radius || distance == 0.0 {
+        return Vector3::new(0.0, 0.0, 0.0);
     }
+
+    let gravity_force = stats.gravity_max_force * match stats.falloff {
+        Falloff::Linear        => 1.0 - distance/stats.gravity_radius,
+        Falloff::InverseSquare => (SCALE / distance).powi(2).min(1.0),
+        Falloff::Constant      => 1.0,
+    };
+
+    offset / distance * gravity_force
 }
 
-pub fn spawn(
-    world: &mut World,
-    assets: &mut Assets,
+// Adds the beam rigid body and collider to the physics sets
+pub fn insert_body(
     bodies: &mut RigidBodySet,
     colliders: &mut ColliderSet,
+    hull: Option<&[na::Point3<f32>]>,
     position: Point3,
-) {
-    let texture = assets.register("energy_beam::texture");
-    let mesh = assets.register("energy_beam::mesh");
-
+) -> RigidBodyHandle {
     let rigid_body = RigidBodyBuilder::new(BodyStatus::Static)
         .translation(position.x, position.y, position.z)
         .build();
 
-    let collider = ColliderBuilder::ball(1.0 * SCALE)
+    let collider = hull::collider(hull, MODEL_SCALE, 1.0 * SCALE, None)
         .build();
 
     let body_handle = bodies.insert(rigid_body);
 
     colliders.insert(collider, body_handle, bodies);
 
+    body_handle
+}
+
+pub fn spawn(
+    world: &mut World,
+    assets: &mut Assets,
+    bodies: &mut RigidBodySet,
+    colliders: &mut ColliderSet,
+    hulls: &hull::Hulls,
+    position: Point3,
+) {
+    let texture = assets.register("energy_beam::texture");
+    let mesh = assets.register("energy_beam::mesh");
+
+    let body_handle = insert_body(bodies, colliders, hulls.get("energy_beam"), position);
+
     world.spawn(Some((
         Model::from(mesh),
         Material {
@@ -110,11 +197,151 @@ pub fn spawn(
         },
         Transform {
             translate: Vec3::new(position.x, position.y, position.z),
-            scale: Vec3::new(0.57 * SCALE, 0.57 * SCALE, 0.57 * SCALE),
+            scale: Vec3::new(MODEL_SCALE, MODEL_SCALE, MODEL_SCALE),
             ..Default::default()
         },
         body_handle,
         Stats { ..Default::default() },
         Pipeline::default(),
     )));
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drone;
+
+    use rapier3d::geometry::ColliderBuilder;
+
+    use proptest::prelude::*;
+
+    // ball body with the forces recorded for the debug draw
+    fn insert_ball(
+        world: &mut World,
+        bodies: &mut RigidBodySet,
+        colliders: &mut ColliderSet,
+        status: BodyStatus,
+        z: f32,
+    ) -> RigidBodyHandle {
+        let body = bodies.insert(RigidBodyBuilder::new(status).translation(0.0, 0.0, z).build());
+        colliders.insert(ColliderBuilder::ball(1.0).build(), body, bodies);
+        world.spawn(Some((body, physics::Forces::default())));
+        body
+    }
+
+    #[test]
+    fn gravity_pulls_dynamic_bodies_in_the_radius() {
+        let mut world = World::new();
+        let mut bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+        let mut query_pipeline = QueryPipeline::new();
+        let stats = Stats::default();
+
+        let beam = insert_body(&mut bodies, &mut colliders, None, Point3::new(0.0, 0.0, 0.0));
+        world.spawn(Some((beam, stats.clone(), physics::Forces::default())));
+
+        let drone = drone::insert_body(&mut bodies, &mut colliders, None, Point3::new(0.0, 0.0, 30.0));
+        world.spawn(Some((drone, physics::Forces::default())));
+
+        let wall = insert_ball(&mut world, &mut bodies, &mut colliders, BodyStatus::Static, -30.0);
+        let far = insert_ball(
+            &mut world, &mut bodies, &mut colliders, BodyStatus::Dynamic, stats.gravity_radius * 2.0
+        );
+
+        query_pipeline.update(&bodies, &colliders);
+        apply_gravity(&world, &mut bodies, &colliders, &query_pipeline);
+
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        let pull = force(&zero, &Vector3::new(0.0, 0.0, 30.0), &stats);
+
+        assert!(pull != zero);
+
+        for (rigid_body, forces) in world.query::<( &RigidBodyHandle, &physics::Forces )>() {
+            if *rigid_body == drone {
+                assert_eq!(forces.gravity, pull);
+            } else {
+                // the beam itself, the static wall and the body out of reach
+                assert!([beam, wall, far].contains(rigid_body));
+                assert_eq!(forces.gravity, zero);
+            }
+        }
+    }
+
+    #[test]
+    fn force_points_to_the_beam() {
+        let stats = Stats::default();
+        let beam_position = Vector3::new(0.0, 0.0, 0.0);
+        let position = Vector3::new(0.0, 0.0, stats.gravity_radius / 2.0);
+
+        let force = force(&beam_position, &position, &stats);
+
+        assert_eq!(force, Vector3::new(0.0, 0.0, -stats.gravity_max_force / 2.0));
+    }
+
+    #[test]
+    fn no_force_outside_radius() {
+        let stats = Stats::default();
+        let beam_position = Vector3::new(10.0, 0.0, 0.0);
+
+        assert_eq!(
+            force(&beam_position, &Vector3::new(10.0, 0.0, stats.gravity_radius), &stats),
+            Vector3::new(0.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn no_force_at_center() {
+        let beam_position = Vector3::new(10.0, 0.0, 0.0);
+
+        for falloff in [Falloff::Linear, Falloff::InverseSquare, Falloff::Constant].iter() {
+            let stats = Stats { falloff: *falloff, ..Default::default() };
+
+            assert_eq!(
+                force(&beam_position, &beam_position, &stats),
+                Vector3::new(0.0, 0.0, 0.0)
+            );
+        }
+    }
+
+    #[test]
+    fn falloff_curves() {
+        let beam_position = Vector3::new(0.0, 0.0, 0.0);
+        let position = Vector3::new(SCALE * 2.0, 0.0, 0.0);
+
+        let linear = Stats::default();
+        let inverse_square = Stats { falloff: Falloff::InverseSquare, ..Default::default() };
+        let constant = Stats { falloff: Falloff::Constant, ..Default::default() };
+
+        let max = linear.gravity_max_force;
+        let ratio = SCALE * 2.0 / linear.gravity_radius;
+
+        assert_eq!(force(&beam_position, &position, &linear).x, -max * (1.0 - ratio));
+        assert_eq!(force(&beam_position, &position, &inverse_square).x, -max / 4.0);
+        assert_eq!(force(&beam_position, &position, &constant).x, -max);
+
+        // inside the beam the inverse square force doesn't grow further
+        let inside = Vector3::new(SCALE / 2.0, 0.0, 0.0);
+        assert_eq!(force(&beam_position, &inside, &inverse_square).x, -max);
+    }
+
+    proptest! {
+        #[test]
+        fn force_is_finite_and_bounded(
+            position in prop::array::uniform3(-500.0f32..500.0),
+            falloff in prop_oneof![
+                Just(Falloff::Linear),
+                Just(Falloff::InverseSquare),
+                Just(Falloff::Constant),
+            ],
+        ) {
+            let stats = Stats { falloff, ..Default::default() };
+            let position = Vector3::new(position[0], position[1], position[2]);
+
+            let force = force(&Vector3::new(0.0, 0.0, 0.0), &position, &stats);
+
+            prop_assert!(force.iter().all(|f| f.is_finite()));
+            prop_assert!(force.norm() <= stats.gravity_max_force + 1e-4);
+            prop_assert!(force.dot(&position) <= 0.0);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// Headless authoritative server for the networked game
+//
+//     cargo run --bin server -- --port 7777 --bots 10 --level assets/levels/arena.ron
+//
+// `--latency MS --jitter MS --loss P` simulate a bad network on localhost
+
+use drone_target::{ arena, drone, hull, net, server };
+
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+fn main() {
+    let mut port = net::DEFAULT_PORT;
+    let mut bots = 10;
+    let mut level_path = String::from(arena::DEFAULT_LEVEL);
+    let mut conditions = net::Conditions::default();
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let value = args.next();
+
+        match (arg.as_str(), value) {
+            ("--port", Some(value))  => port = value.parse().expect("Invalid port"),
+            ("--bots", Some(value))  => bots = value.parse().expect("Invalid bot count"),
+            ("--level", Some(value)) => level_path = value,
+            ("--latency", Some(value)) => conditions.latency =
+                Duration::from_millis(value.parse().expect("Invalid latency")),
+            ("--jitter", Some(value)) => conditions.jitter =
+                Duration::from_millis(value.parse().expect("Invalid jitter")),
+            ("--loss", Some(value)) => conditions.loss = value.parse().expect("Invalid loss"),
+            _ => {
+                println!("Usage: server [--port PORT] [--bots COUNT] [--level PATH] \
+                    [--latency MS] [--jitter MS] [--loss P]");
+                return;
+            },
+        }
+    }
+
+    let level = arena::Level::load(&level_path).unwrap_or_else(|e| {
+        println!("{}, using an empty level", e);
+        arena::Level::default()
+    });
+
+    let mut hulls = hull::Hulls::default();
+
+    for (name, path) in hull::MODELS.iter() {
+        if let Err(e) = hulls.load(name, path) {
+            println!("{}, using a ball collider", e);
+        }
+    }
+
+    let mut simulation = server::Simulation::new(&level, hulls);
+
+    for _ in 0..bots {
+        simulation.spawn_drone(drone::Stats { aggression: 1.0, charge: 50.0, ..Default::default() });
+    }
+
+    let mut server = server::Server::bind(("0.0.0.0", port), simulation)
+        .expect("Could not bind the server socket");
+
+    server.simulate(conditions);
+
+    println!("Serving {} on port {}", level.name, port);
+
+    // runs until the process is stopped
+    server.run(&AtomicBool::new(false));
+}
@@ -0,0 +1,469 @@
+use super::{ drone, beam, lobby, settings::GameMode, };
+
+use rapier3d::{
+    dynamics::{ RigidBodySet, RigidBodyHandle, },
+    na::{ Vector3, },
+};
+
+use dotrix::{
+    Frame,
+    services::{ World, },
+    math::{ Point3, },
+    overlay::Overlay,
+    ecs::{ Mut, Const, Entity, },
+};
+
+use dotrix::egui::{
+    self,
+    Egui,
+};
+
+use std::f32::consts::PI;
+
+// seconds of holding a beam alone to win
+pub const WINNING_SCORE: f32 = 60.0;
+
+// bots join the fight from this far outside the far zone
+const SPAWN_MARGIN: f32 = 15.0;
+// candidate spawn points tried per bot, some fall into other beams zones
+const SPAWN_CANDIDATES: usize = 4;
+// thrust of the bots contesting the zone on top of their difficulty
+// aggression, enough to fly in against the drag
+pub const CONTEST_AGGRESSION: f32 = 1.0;
+// charge the contesting bots start with to fly into the zone
+pub const BOT_CHARGE: f32 = 50.0;
+
+const BAR_WIDTH: f32 = 200.0;
+const BAR_HEIGHT: f32 = 8.0;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Zone {
+    // nobody is inside the beam zone
+    Neutral,
+    // a single drone is inside and scores
+    Captured(Entity),
+    // several drones are inside, nobody scores
+    Contested,
+}
+
+pub struct Capture {
+    pub scores: Vec<(Entity, f32)>,
+    // zone of every beam
+    pub zones: Vec<Zone>,
+    pub winner: Option<Entity>,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self {
+            scores: Vec::new(),
+            zones: Vec::new(),
+            winner: None,
+        }
+    }
+}
+
+impl Capture {
+    pub fn reset(&mut self) {
+        self.scores.clear();
+        self.zones.clear();
+        self.winner = None;
+    }
+
+    pub fn score(&self, entity: Entity) -> f32 {
+        self.scores.iter()
+            .find(|(e, _)| *e == entity)
+            .map_or(0.0, |(_, score)| *score)
+    }
+
+    // Adds points to the drone, returns its new score
+    fn add(&mut self, entity: Entity, points: f32) -> f32 {
+        match self.scores.iter_mut().find(|(e, _)| *e == entity) {
+            Some((_, score)) => {
+                *score = *score + points;
+                *score
+            },
+            None => {
+                self.scores.push((entity, points));
+                points
+            }
+        }
+    }
+
+    // Zone shown to the player: the one it captures, else a contested one,
+    // else one held by a bot
+    pub fn shown(&self, player: Option<Entity>) -> Zone {
+        if let Some(player) = player {
+            if self.zones.contains(&Zone::Captured(player)) {
+                return Zone::Captured(player);
+            }
+        }
+
+        if self.zones.contains(&Zone::Contested) {
+            return Zone::Contested;
+        }
+
+        self.zones.iter()
+            .copied()
+            .find(|zone| *zone != Zone::Neutral)
+            .unwrap_or(Zone::Neutral)
+    }
+
+    // drone with the highest score
+    pub fn leader(&self) -> Option<(Entity, f32)> {
+        self.scores.iter()
+            .copied()
+            .fold(None, |leader, (entity, score)| match leader {
+                Some((_, best)) if best >= score => leader,
+                _ => Some((entity, score)),
+            })
+    }
+}
+
+// Bot spawn points just outside the far zone of the beams, spread evenly
+// around each beam and away from the zones of the others
+pub fn spawn_points(beams: &[(f32, f32, f32)], radius_far: f32, count: usize) -> Vec<Point3> {
+    if beams.is_empty() {
+        return Vec::new();
+    }
+
+    let distance = radius_far + SPAWN_MARGIN;
+    let candidates = count * SPAWN_CANDIDATES;
+    // points of a Fibonacci sphere around every beam
+    let per_beam = candidates / beams.len() + 1;
+    let golden_angle = PI * (3.0 - 5.0f32.sqrt());
+
+    let beams: Vec<Vector3<f32>> = beams.iter()
+        .map(|(x, y, z)| Vector3::new(*x, *y, *z))
+        .collect();
+
+    (0..candidates)
+        .map(|i| {
+            let beam = beams[i % beams.len()];
+            let k = (i / beams.len()) as f32;
+
+            let y = 1.0 - 2.0 * (k + 0.5) / per_beam as f32;
+            let r = (1.0 - y * y).sqrt();
+            let theta = k * golden_angle;
+
+            beam + Vector3::new(r * theta.cos(), y, r * theta.sin()) * distance
+        })
+        .filter(|point| beams.iter().all(|beam| drone::distance(point, beam) > radius_far))
+        .take(count)
+        .map(|point| Point3::new(point.x, point.y, point.z))
+        .collect()
+}
+
+// Zone state of a beam by the drones inside its medium radius
+pub fn zone(inside: &[Entity]) -> Zone {
+    match inside {
+        []       => Zone::Neutral,
+        [entity] => Zone::Captured(*entity),
+        _        => Zone::Contested,
+    }
+}
+
+pub fn update(
+    world: Const<World>,
+    bodies: Const<RigidBodySet>,
+    active: Const<lobby::Active>,
+    frame: Const<Frame>,
+    mut capture: Mut<Capture>,
+) {
+    if active.mode != GameMode::KingOfTheBeam {
+        return;
+    }
+
+    apply_capture(&world, &bodies, &mut capture, 1.0 / frame.fps());
+}
+
+// Scores every drone holding a beam zone alone
+pub fn apply_capture(
+    world: &World,
+    bodies: &RigidBodySet,
+    capture: &mut Capture,
+    dt: f32,
+) {
+    if capture.winner.is_some() {
+        return;
+    }
+
+    capture.zones.clear();
+
+    let beams_query = world.query::<( &mut RigidBodyHandle, &mut beam::Stats )>();
+
+    for (beam_rigid_body, beam_stats) in beams_query {
+        let beam_position = bodies.get(*beam_rigid_body).unwrap()
+            .position().translation.vector;
+
+        let mut inside = Vec::new();
+
+        let query = world.query::<( &Entity, &mut RigidBodyHandle, &mut drone::Stats )>();
+
+        for (entity, rigid_body, _) in query {
+            let position = bodies.get(*rigid_body).unwrap().position().translation.vector;
+
+            if drone::distance(&position, &beam_position) < beam_stats.radius_medium {
+                inside.push(*entity);
+            }
+        }
+
+        capture.zones.push(zone(&inside));
+    }
+
+    // a drone alone in the zones of several beams scores once
+    let mut holders: Vec<Entity> = Vec::new();
+
+    for zone in capture.zones.iter() {
+        if let Zone::Captured(entity) = zone {
+            if !holders.contains(entity) {
+                holders.push(*entity);
+            }
+        }
+    }
+
+    for entity in holders {
+        // the first drone over the threshold wins
+        if capture.add(entity, dt) >= WINNING_SCORE && capture.winner.is_none() {
+            capture.winner = Some(entity);
+        }
+    }
+}
+
+// Draws the score bar with a mark for the winning threshold
+fn score_bar(ui: &mut egui::Ui, label: &str, score: f32, color: egui::Color32) {
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::Label::new(label)
+                .text_color(egui::Color32::LIGHT_GRAY)
+                .strong()
+        );
+
+        let (rect, _) = ui.allocate_exact_size(
+            egui::Vec2::new(BAR_WIDTH, BAR_HEIGHT),
+            egui::Sense::hover(),
+        );
+
+        let progress = (score / WINNING_SCORE).min(1.0);
+        let filled = egui::Rect::from_min_size(
+            rect.min,
+            egui::Vec2::new(rect.width() * progress, rect.height()),
+        );
+
+        ui.painter().rect_filled(rect, 1.0, egui::Color32::from_gray(48));
+        ui.painter().rect_filled(filled, 1.0, color);
+    });
+}
+
+pub fn ui(
+    world: Const<World>,
+    overlay: Const<Overlay>,
+    active: Const<lobby::Active>,
+    capture: Const<Capture>,
+) {
+    if active.mode != GameMode::KingOfTheBeam {
+        return;
+    }
+
+    // the panel shows the score of the first player
+    let mut player = None;
+
+    for (entity, stats) in world.query::<( &Entity, &drone::Stats )>() {
+        if stats.is_primary() {
+            player = Some(*entity);
+        }
+    }
+
+    let egui = overlay.get::<Egui>()
+        .expect("Renderer does not contain an Overlay instance");
+
+    let capture_ui_frame = egui::containers::Frame{
+        fill: egui::Color32::from_black_alpha(192),
+        corner_radius: 2.5,
+        margin: egui::Vec2::new(4.0, 4.0),
+        ..Default::default()
+    };
+
+    let margin = 8.0;
+
+    egui::containers::Window::new("capture")
+        .anchor(egui::Align2::CENTER_TOP, egui::Vec2::new(0.0, margin))
+        .collapsible(false)
+        .title_bar(false)
+        .resizable(false)
+        .frame(capture_ui_frame)
+        .show(&egui.ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                let (status, color) = match (capture.winner, capture.shown(player)) {
+                    (Some(winner), _) if Some(winner) == player =>
+                        ("YOU WIN", egui::Color32::GREEN),
+                    (Some(_), _) =>
+                        ("A BOT WINS", egui::Color32::RED),
+                    (None, Zone::Captured(entity)) if Some(entity) == player =>
+                        ("CAPTURING", egui::Color32::GREEN),
+                    (None, Zone::Captured(_)) =>
+                        ("BEAM IS HELD BY A BOT", egui::Color32::RED),
+                    (None, Zone::Contested) =>
+                        ("CONTESTED", egui::Color32::YELLOW),
+                    (None, Zone::Neutral) =>
+                        ("BEAM IS FREE", egui::Color32::LIGHT_GRAY),
+                };
+
+                ui.add(egui::Label::new(status).text_color(color).heading());
+            });
+
+            let player_score = player.map_or(0.0, |entity| capture.score(entity));
+            score_bar(ui, "YOU   ", player_score, egui::Color32::GREEN);
+
+            let leader = capture.leader()
+                .filter(|(entity, _)| Some(*entity) != player)
+                .map_or(0.0, |(_, score)| score);
+            score_bar(ui, "LEADER", leader, egui::Color32::RED);
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rapier3d::geometry::ColliderSet;
+
+    // entities of a world with `count` drones
+    fn drones(world: &mut World, count: usize) -> Vec<Entity> {
+        for _ in 0..count {
+            world.spawn(Some((drone::Stats::default(),)));
+        }
+
+        world.query::<( &Entity, &drone::Stats )>()
+            .map(|(entity, _)| *entity)
+            .collect()
+    }
+
+    #[test]
+    fn zone_states() {
+        let mut world = World::new();
+        let entities = drones(&mut world, 2);
+
+        assert_eq!(zone(&[]), Zone::Neutral);
+        assert_eq!(zone(&entities[..1]), Zone::Captured(entities[0]));
+        assert_eq!(zone(&entities), Zone::Contested);
+    }
+
+    #[test]
+    fn scores_add_up_and_the_best_leads() {
+        let mut world = World::new();
+        let entities = drones(&mut world, 2);
+        let mut capture = Capture::default();
+
+        assert_eq!(capture.leader(), None);
+
+        assert_eq!(capture.add(entities[0], 2.0), 2.0);
+        assert_eq!(capture.add(entities[1], 3.0), 3.0);
+        assert_eq!(capture.add(entities[0], 2.0), 4.0);
+
+        assert_eq!(capture.score(entities[0]), 4.0);
+        assert_eq!(capture.scores.len(), 2);
+        assert_eq!(capture.leader(), Some((entities[0], 4.0)));
+    }
+
+    #[test]
+    fn drones_alone_at_different_beams_both_score() {
+        let mut world = World::new();
+        let mut bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+
+        for x in [-200.0, 200.0].iter() {
+            let body = beam::insert_body(&mut bodies, &mut colliders, None, Point3::new(*x, 0.0, 0.0));
+            world.spawn(Some((body, beam::Stats::default())));
+
+            let body = drone::insert_body(&mut bodies, &mut colliders, None, Point3::new(*x, 30.0, 0.0));
+            world.spawn(Some((body, drone::Stats::default())));
+        }
+
+        let mut capture = Capture::default();
+        apply_capture(&world, &bodies, &mut capture, 1.0);
+
+        assert_eq!(capture.zones.len(), 2);
+        assert_eq!(capture.scores.len(), 2);
+
+        for (entity, score) in capture.scores.iter() {
+            assert_eq!(*score, 1.0);
+            assert!(capture.zones.contains(&Zone::Captured(*entity)));
+        }
+    }
+
+    #[test]
+    fn the_player_sees_its_own_zone_first() {
+        let mut world = World::new();
+        let entities = drones(&mut world, 2);
+        let (player, bot) = (Some(entities[0]), entities[1]);
+
+        let mut capture = Capture::default();
+        assert_eq!(capture.shown(player), Zone::Neutral);
+
+        capture.zones = vec![Zone::Neutral, Zone::Captured(bot)];
+        assert_eq!(capture.shown(player), Zone::Captured(bot));
+
+        capture.zones = vec![Zone::Captured(bot), Zone::Contested];
+        assert_eq!(capture.shown(player), Zone::Contested);
+
+        capture.zones = vec![Zone::Contested, Zone::Captured(entities[0])];
+        assert_eq!(capture.shown(player), Zone::Captured(entities[0]));
+    }
+
+    #[test]
+    fn bots_spawn_outside_the_far_zones() {
+        let far = beam::Stats::default().radius_far;
+        let beams = [(0.0, 0.0, 0.0), (150.0, 0.0, 0.0)];
+
+        let points = spawn_points(&beams, far, 20);
+
+        assert_eq!(points.len(), 20);
+
+        for point in points.iter() {
+            for (x, y, z) in beams.iter() {
+                let distance = drone::distance(
+                    &Vector3::new(point.x, point.y, point.z),
+                    &Vector3::new(*x, *y, *z),
+                );
+                assert!(distance > far);
+            }
+        }
+    }
+
+    #[test]
+    fn lone_player_in_the_zone_wins() {
+        let mut world = World::new();
+        let mut bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+
+        let stats = beam::Stats::default();
+        let body = beam::insert_body(&mut bodies, &mut colliders, None, Point3::new(0.0, 0.0, 0.0));
+        world.spawn(Some((body, stats.clone())));
+
+        let body = drone::insert_body(&mut bodies, &mut colliders, None, Point3::new(30.0, 0.0, 0.0));
+        world.spawn(Some((body, drone::Stats { is_player: true, ..Default::default() })));
+
+        // the bots of the mode start outside the zone
+        for point in spawn_points(&[(0.0, 0.0, 0.0)], stats.radius_far, 20) {
+            let body = drone::insert_body(&mut bodies, &mut colliders, None, point);
+            world.spawn(Some((body, drone::Stats { capturer: true, ..Default::default() })));
+        }
+
+        let player = world.query::<( &Entity, &drone::Stats )>()
+            .find(|(_, stats)| stats.is_player)
+            .map(|(entity, _)| *entity)
+            .unwrap();
+
+        let mut capture = Capture::default();
+        let dt = 0.25;
+
+        for _ in 0..(WINNING_SCORE / dt) as usize {
+            apply_capture(&world, &bodies, &mut capture, dt);
+        }
+
+        assert_eq!(capture.zones, vec![Zone::Captured(player)]);
+        assert_eq!(capture.winner, Some(player));
+        assert!(capture.score(player) >= WINNING_SCORE);
+    }
+}
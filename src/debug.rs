@@ -0,0 +1,159 @@
+use super::{ physics, settings, view };
+
+use rapier3d::{
+    dynamics::{ RigidBodySet, RigidBodyHandle, },
+    geometry::{ Collider, ColliderSet, },
+    na::{ Point3, Vector3, },
+};
+
+use dotrix::{
+    services::{ Camera, World, },
+    overlay::Overlay,
+    ecs::{ Const, },
+};
+
+use dotrix::egui::{
+    self,
+    Egui,
+};
+
+// length of the drawn vectors per unit of velocity, force and impulse
+const VELOCITY_SCALE: f32 = 0.2;
+const FORCE_SCALE:    f32 = 2.0;
+const IMPULSE_SCALE:  f32 = 0.05;
+
+const COLLIDER_COLOR: egui::Color32 = egui::Color32::from_rgb(64, 255, 64);
+const VELOCITY_COLOR: egui::Color32 = egui::Color32::from_rgb(64, 160, 255);
+const THRUST_COLOR:   egui::Color32 = egui::Color32::from_rgb(255, 255, 255);
+const DRAG_COLOR:     egui::Color32 = egui::Color32::from_rgb(255, 64, 64);
+const GRAVITY_COLOR:  egui::Color32 = egui::Color32::from_rgb(200, 64, 255);
+const STRIKE_COLOR:   egui::Color32 = egui::Color32::from_rgb(255, 200, 0);
+
+// Draws the collider wireframes, body velocities and the forces applied to
+// the drones over the scene
+pub fn draw(
+    world: Const<World>,
+    overlay: Const<Overlay>,
+    bodies: Const<RigidBodySet>,
+    colliders: Const<ColliderSet>,
+    camera: Const<Camera>,
+    settings: Const<settings::Settings>,
+) {
+    if !settings.debug_draw {
+        return;
+    }
+
+    let egui = overlay.get::<Egui>()
+        .expect("Renderer does not contain an Overlay instance");
+
+    let screen = egui.ctx.input().screen_rect();
+    let painter = egui.ctx.layer_painter(
+        egui::LayerId::new(egui::Order::Background, egui::Id::new("debug_draw"))
+    );
+
+    let segment = |a: Vector3<f32>, b: Vector3<f32>, color: egui::Color32| {
+        let a = view::to_screen(&camera, a, screen);
+        let b = view::to_screen(&camera, b, screen);
+
+        if let (Some(a), Some(b)) = (a, b) {
+            painter.line_segment([a, b], egui::Stroke::new(1.0, color));
+        }
+    };
+
+    for (_, collider) in colliders.iter() {
+        for (a, b) in wireframe(collider) {
+            segment(a, b, COLLIDER_COLOR);
+        }
+
+        // balls are drawn as their outline
+        if let Some(ball) = collider.shape().as_ball() {
+            let center = collider.position().translation.vector;
+
+            let position = view::to_screen(&camera, center, screen);
+            let radius = view::screen_radius(&camera, center, ball.radius, screen);
+
+            if let (Some(position), Some(radius)) = (position, radius) {
+                painter.circle_stroke(position, radius, egui::Stroke::new(1.0, COLLIDER_COLOR));
+            }
+        }
+    }
+
+    for (_, body) in bodies.iter() {
+        if body.is_dynamic() {
+            let position = body.position().translation.vector;
+            segment(position, position + body.linvel() * VELOCITY_SCALE, VELOCITY_COLOR);
+        }
+    }
+
+    let query = world.query::<( &mut RigidBodyHandle, &mut physics::Forces )>();
+
+    for (rigid_body, forces) in query {
+        let position = bodies.get(*rigid_body).unwrap().position().translation.vector;
+
+        segment(position, position + forces.thrust * FORCE_SCALE, THRUST_COLOR);
+        segment(position, position + forces.drag * FORCE_SCALE, DRAG_COLOR);
+        segment(position, position + forces.gravity * FORCE_SCALE, GRAVITY_COLOR);
+        segment(position, position + forces.strike * IMPULSE_SCALE, STRIKE_COLOR);
+    }
+}
+
+// Edges of cuboid and convex hull colliders in the world space
+fn wireframe(collider: &Collider) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+    let position = collider.position();
+    let shape = collider.shape();
+
+    if let Some(polyhedron) = shape.as_convex_polyhedron() {
+        let points = polyhedron.points();
+
+        return polyhedron.edges().iter()
+            .map(|edge| (
+                (position * points[edge.vertices.x as usize]).coords,
+                (position * points[edge.vertices.y as usize]).coords,
+            ))
+            .collect();
+    }
+
+    if let Some(cuboid) = shape.as_cuboid() {
+        let h = cuboid.half_extents;
+        let corner = |i: usize| (position * Point3::new(
+            if i & 1 == 0 { -h.x } else { h.x },
+            if i & 2 == 0 { -h.y } else { h.y },
+            if i & 4 == 0 { -h.z } else { h.z },
+        )).coords;
+
+        // corners differing in a single axis are connected
+        let mut edges = Vec::new();
+
+        for i in 0..8 {
+            for axis in &[1, 2, 4] {
+                if i & axis == 0 {
+                    edges.push((corner(i), corner(i | axis)));
+                }
+            }
+        }
+
+        return edges;
+    }
+
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rapier3d::geometry::ColliderBuilder;
+
+    #[test]
+    fn cuboid_wireframe_has_twelve_edges() {
+        let collider = ColliderBuilder::cuboid(1.0, 2.0, 3.0).build();
+        let edges = wireframe(&collider);
+
+        assert_eq!(edges.len(), 12);
+        // every edge is parallel to an axis
+        for (a, b) in edges {
+            let length = (b - a).norm();
+            assert!([2.0, 4.0, 6.0].contains(&length));
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use dotrix::egui::{
+    self,
+    CtxRef,
+};
+
+struct Question<T> {
+    title: String,
+    message: String,
+    answers: Vec<(String, T)>,
+}
+
+// Modal question with a button per answer and a cancel button, the caller
+// shows nothing else while it is open
+pub struct Dialog<T> {
+    question: Option<Question<T>>,
+}
+
+impl<T> Default for Dialog<T> {
+    fn default() -> Self {
+        Self {
+            question: None,
+        }
+    }
+}
+
+impl<T: Copy> Dialog<T> {
+    // Opens the dialog, replacing the question asked before
+    pub fn ask(&mut self, title: &str, message: &str, answers: &[(&str, T)]) {
+        self.question = Some(Question {
+            title: String::from(title),
+            message: String::from(message),
+            answers: answers.iter()
+                .map(|(label, answer)| (String::from(*label), *answer))
+                .collect(),
+        });
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.question.is_some()
+    }
+
+    pub fn close(&mut self) {
+        self.question = None;
+    }
+
+    // Closes the dialog with the answer of the button
+    fn answer(&mut self, button: usize) -> Option<T> {
+        let answer = self.question.as_ref()
+            .and_then(|question| question.answers.get(button))
+            .map(|(_, answer)| *answer);
+
+        self.close();
+        answer
+    }
+
+    // Draws the open dialog, returns the answer once a button is clicked
+    pub fn show(&mut self, ctx: &CtxRef) -> Option<T> {
+        let question = self.question.as_ref()?;
+
+        // the last button cancels
+        let mut clicked = None;
+
+        egui::containers::Window::new(&question.title)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::new(0.0, 0.0))
+            .collapsible(false)
+            .resizable(false)
+            .default_width(200.0)
+            .show(ctx, |ui| {
+                ui.vertical_centered_justified(|ui| {
+                    ui.label(&question.message);
+
+                    for (button, (label, _)) in question.answers.iter().enumerate() {
+                        if ui.button(label).clicked() {
+                            clicked = Some(button);
+                        }
+                    }
+
+                    if ui.button("Cancel").clicked() {
+                        clicked = Some(question.answers.len());
+                    }
+                });
+            });
+
+        clicked.and_then(|button| self.answer(button))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answering_closes_the_dialog() {
+        let mut dialog = Dialog::default();
+        assert!(!dialog.is_open());
+
+        dialog.ask("Reset", "Restart the match?", &[("Restart", 1), ("New match", 2)]);
+        assert!(dialog.is_open());
+
+        assert_eq!(dialog.answer(1), Some(2));
+        assert!(!dialog.is_open());
+
+        // the cancel button follows the answers
+        dialog.ask("Exit", "Exit the game?", &[("Exit", 3)]);
+        assert_eq!(dialog.answer(1), None);
+        assert!(!dialog.is_open());
+    }
+}
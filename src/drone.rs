@@ -1,24 +1,28 @@
 use super::{ Action, ToExile};
-use super::settings;
+use super::{ ability, hull, local, physics, settings, targeting, view };
 
 use rapier3d::{
     dynamics::{
-        RigidBodyBuilder, BodyStatus, RigidBodySet, RigidBodyHandle, JointSet,
+        RigidBody, RigidBodyBuilder, BodyStatus, RigidBodySet, RigidBodyHandle,
     },
-    geometry::{ ColliderSet, ColliderBuilder, },
+    geometry::{ ColliderHandle, ColliderSet, NarrowPhase, },
     na::{ Vector3, geometry::UnitQuaternion, },
     na,
 };
 
 use dotrix::{
+    Frame,
     Transform,
     Pipeline,
     pbr:: { Model, Material, },
     services::{ Assets, World, Camera, Input, },
     math::{ Point3, Vec3, Quat, },
-    ecs::{ Mut, Const, Entity, },
+    ecs::{ Mut, Const, Context, Entity, },
 };
 
+use serde::{ Serialize, Deserialize, };
+
+use std::collections::HashMap;
 use std::f32::consts::PI;
 
 use crate::beam;
@@ -26,9 +30,14 @@ use crate::beam;
 #[derive(Debug)]
 pub struct Stats {
     pub is_player:     bool,
+    pub slot:          usize, // local player slot, 0 for the mouse and keyboard player
     pub charge:        f32,  // drone battery state of charge (0-100%)
     pub strike_charge: f32,  // energy to be used when strike is activated (0-100%)
     pub health:        f32,
+    pub aggression:    f32,  // force bots pursue the player with
+    pub capturer:      bool, // bots flying to the beam zones instead of the players
+    pub boost:         f32,  // seconds left of the speed boost
+    pub shield:        f32,  // portion of the damage absorbed (0-1)
     pub x:             f32,
     pub y:             f32,
     pub z:             f32,
@@ -39,9 +48,14 @@ impl Default for Stats {
     fn default() -> Self {
         Self {
             is_player:     false,
+            slot:              0,
             charge:          0.0,
             strike_charge:   0.0,
             health:        100.0,
+            aggression:      0.0,
+            capturer:      false,
+            boost:           0.0,
+            shield:          0.0,
             x:               0.0,
             y:               0.0,
             z:               0.0,
@@ -50,137 +64,344 @@ impl Default for Stats {
     }
 }
 
+impl Stats {
+    // the player steering with the mouse, followed by the camera and the HUD
+    pub fn is_primary(&self) -> bool {
+        self.is_player & (self.slot == 0)
+    }
+}
+
+// Flight controls of a player drone for a single frame
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Intent {
+    pub forward:    bool,
+    pub backward:   bool,
+    pub left:       bool,
+    pub right:      bool,
+    pub accelerate: bool,
+    pub strike:     bool, // strike is being charged
+    pub release:    bool, // strike is released this frame
+    pub y_angle:    f32,  // heading to turn the drone to, in camera angles
+    pub xz_angle:   f32,
+}
+
+impl Intent {
+    // controls of the primary player from the mapped actions and the camera
+    pub fn from_input(input: &Input, camera: &Camera) -> Self {
+        Self {
+            forward:    input.is_action_hold(Action::MoveForward),
+            backward:   input.is_action_hold(Action::MoveBackward),
+            left:       input.is_action_hold(Action::MoveLeft),
+            right:      input.is_action_hold(Action::MoveRight),
+            accelerate: input.is_action_hold(Action::Accelerate),
+            strike:     input.is_action_hold(Action::Strike),
+            release:    input.is_action_deactivated(Action::Strike),
+            y_angle:    camera.y_angle,
+            xz_angle:   camera.xz_angle,
+        }
+    }
+}
+
 const D_CHARGE:        f32 = 0.5;
 const D_HEALTH:        f32 = 0.2;
 const D_MOVE_CHARGE:   f32 = 0.05;
 const D_ACC_CHARGE:    f32 = 0.25;
+const BOOST_FACTOR:    f32 = 2.0;
 const D_STRIKE_CHARGE: f32 = 0.5;
-const MAX_CHARGE:      f32 = 100.0;
+pub const MAX_CHARGE:  f32 = 100.0;
 const VELO_MIN:        f32 = 10.0;
+pub const STRIKE_IMPULSE: f32 = 2.0;
+// collisions slower than this don't hurt
+const SAFE_IMPACT_SPEED: f32 = 5.0;
+const D_IMPACT_HEALTH:   f32 = 2.0;
+// drone model scale, the collider is built from the scaled model
+pub const MODEL_SCALE: f32 = 1.18;
+// mass of the former unit ball collider with the density of 0.02
+const MASS: f32 = 0.02 * 4.0 / 3.0 * PI;
 
 pub fn control(
     world: Const<World>,
     mut bodies: Mut<RigidBodySet>,
     input: Const<Input>,
-    mut camera: Mut<Camera>,
+    camera: Const<Camera>,
+    rig: Const<view::Rig>,
     settings: Const<settings::Settings>,
+    lock_on: Const<targeting::LockOn>,
+    local: Const<local::Local>,
+    frame: Const<Frame>,
     mut to_exile: Mut<ToExile>,
 ) {
     // Query drone entities
-    let query = world.query::<(
-        &Entity, &mut Transform, &mut RigidBodyHandle, &mut Stats
-    )>();
+    let query = world.query::<( &mut RigidBodyHandle, &mut Stats, &mut physics::Forces )>();
 
-    for (entity, transform, rigid_body, stats) in query {
+    for (rigid_body, stats, forces) in query {
+        // the camera mode of the first player doesn't stop the others
+        if !stats.is_player | (stats.is_primary() & !rig.controls_player()) {
+            continue;
+        }
 
         let body = bodies.get_mut(*rigid_body).unwrap();
-        let position = body.position().translation;
 
-        //TO DO: rethink dw1 and dw2 usage
-        let dw1 = UnitQuaternion::from_euler_angles(0.0, 0.0, -PI/2.0);
-        let rotation = body.position().rotation * dw1.inverse();
+        let intent = if stats.is_primary() {
+            Intent::from_input(&input, &camera)
+        } else {
+            local.intent(&input, stats.slot)
+        };
 
-        if stats.is_player {
-            let target_xz_angle = camera.xz_angle;
-            let target_y_angle = camera.y_angle;
-
-            //TO DO: rethink PI/2.0 shift
-            let target_rotation = UnitQuaternion::from_euler_angles(
-                0.0,
-                -target_xz_angle,
-                PI/2.0 - target_y_angle
-            );
+        let intercept = match (settings.aim_assist, lock_on.slots.get(stats.slot)) {
+            (true, Some(lock)) => lock.intercept,
+            _                  => None,
+        };
+
+        fly(body, stats, forces, &intent, intercept);
+    }
 
-            let delta_rotation = target_rotation * rotation.inverse();
-            let delta_axis = match delta_rotation.axis() {
-                Some(x) => Vector3::new(
-                    x.into_inner().data[0],
-                    x.into_inner().data[1],
-                    x.into_inner().data[2],
-                ),
-                None    => Vector3::new(0.0, 0.0, 0.0),
-            };
+    update(&world, &mut bodies, settings.god_mode, 1.0 / frame.fps(), &mut to_exile);
+}
 
-            let delta_angle = delta_rotation.angle();
+// Applies the flight controls of a player to the drone body
+pub fn fly(
+    body: &mut RigidBody,
+    stats: &mut Stats,
+    forces: &mut physics::Forces,
+    intent: &Intent,
+    intercept: Option<Vector3<f32>>,
+) {
+    let position = body.position().translation;
 
-            let rotation_euler = rotation.euler_angles();
+    let rotation = rotation(body);
 
-            let fwd = Vector3::new(
-                -rotation_euler.2.sin() * rotation_euler.1.cos(),
-                rotation_euler.1.sin(),
-                -rotation_euler.2.cos() * rotation_euler.1.cos(),
-            );
+    //TO DO: rethink PI/2.0 shift
+    let target_rotation = UnitQuaternion::from_euler_angles(
+        0.0,
+        -intent.xz_angle,
+        PI/2.0 - intent.y_angle
+    );
 
-            let side = Vector3::new(
-                (-PI/2.0 + rotation_euler.2).sin(),
-                0.0,
-                (-PI/2.0 + rotation_euler.2).cos()
-            );
+    let delta_rotation = target_rotation * rotation.inverse();
+    let delta_axis = match delta_rotation.axis() {
+        Some(x) => Vector3::new(
+            x.into_inner().data[0],
+            x.into_inner().data[1],
+            x.into_inner().data[2],
+        ),
+        None    => Vector3::new(0.0, 0.0, 0.0),
+    };
 
-            let spd = if input.is_action_hold(Action::Accelerate) & (stats.charge >= D_ACC_CHARGE) {
-                stats.charge = stats.charge - D_ACC_CHARGE;
-                10.0
-            } else {
-                1.0
-            };
-
-            let mut dir = Vector3::new(0.0, 0.0, 0.0);
-
-            if input.is_action_hold(Action::MoveForward) {
-                dir = dir + fwd;
-            };
-            if input.is_action_hold(Action::MoveBackward) {
-                dir = dir - fwd;
-            };
-            if input.is_action_hold(Action::MoveLeft) {
-                dir = dir + side;
-            };
-            if input.is_action_hold(Action::MoveRight) {
-                dir = dir - side;
-            };
-
-            let velo = *body.linvel();
-
-            if (dir != Vector3::new(0.0, 0.0, 0.0)) & (stats.charge >= D_MOVE_CHARGE)  {
-                dir = dir.normalize();
-
-                // compensate movement in other directions
-                if velo != Vector3::new(0.0, 0.0, 0.0) {
-                    let comp = velo.normalize().dot(&dir)/dir.dot(&dir)*dir;
-                    dir = dir - (velo.normalize() - comp);
-                    dir = dir.normalize();
-                }
-
-                body.apply_force(dir * spd, true);
-
-                stats.charge = stats.charge - D_MOVE_CHARGE;
-            }
+    let delta_angle = delta_rotation.angle();
+
+    let (fwd, side) = axes(&rotation);
+
+    let spd = if intent.accelerate & (stats.charge >= D_ACC_CHARGE) {
+        stats.charge = stats.charge - D_ACC_CHARGE;
+        10.0
+    } else {
+        1.0
+    } * boost_factor(stats);
+
+    let mut dir = Vector3::new(0.0, 0.0, 0.0);
+
+    if intent.forward {
+        dir = dir + fwd;
+    };
+    if intent.backward {
+        dir = dir - fwd;
+    };
+    if intent.left {
+        dir = dir + side;
+    };
+    if intent.right {
+        dir = dir - side;
+    };
+
+    let velo = *body.linvel();
+
+    forces.thrust = Vector3::new(0.0, 0.0, 0.0);
+
+    if (dir != Vector3::new(0.0, 0.0, 0.0)) & (stats.charge >= D_MOVE_CHARGE)  {
+        dir = dir.normalize();
+
+        // compensate movement in other directions
+        if velo != Vector3::new(0.0, 0.0, 0.0) {
+            let comp = velo.normalize().dot(&dir)/dir.dot(&dir)*dir;
+            dir = dir - (velo.normalize() - comp);
+            dir = dir.normalize();
+        }
+
+        forces.thrust = dir * spd;
+        body.apply_force(forces.thrust, true);
+
+        stats.charge = stats.charge - D_MOVE_CHARGE;
+    }
 
-            // drag force to limit acceleration
-            let speed = (velo.dot(&velo)).sqrt() - VELO_MIN;
-            if speed > 0.0 {
-                let f_drag = -(0.1*speed + 0.002 * speed.powf(2.0)) * velo.normalize();
-                body.apply_force(f_drag, true);
+    // drag force to limit acceleration
+    forces.drag = drag(&velo);
+    body.apply_force(forces.drag, true);
+
+    body.apply_torque(to_model(&delta_axis) * delta_angle * 50.0, true);
+
+    if intent.strike {
+        charge_strike(stats);
+    };
+
+    if intent.release {
+        let direction = targeting::aim(position.vector, fwd, intercept);
+
+        forces.strike = direction * release_strike(stats);
+        body.apply_impulse(forces.strike, true);
+    };
+
+    clamp_charge(stats);
+}
+
+// Contacts of the last frame, a collision damages once when it starts
+#[derive(Default)]
+pub struct Contacts {
+    touching: Vec<(ColliderHandle, ColliderHandle)>,
+    // velocities before the physics step that resolved the contact
+    velocities: HashMap<RigidBodyHandle, Vector3<f32>>,
+}
+
+pub fn collisions(
+    mut context: Context<Contacts>,
+    world: Const<World>,
+    bodies: Const<RigidBodySet>,
+    colliders: Const<ColliderSet>,
+    narrow_phase: Const<NarrowPhase>,
+) {
+    apply_collisions(&world, &bodies, &colliders, &narrow_phase, &mut context);
+}
+
+// Damages drones that hit other bodies, strikes included. Runs after the
+// physics step, the solver has already changed the velocities of the bodies.
+pub fn apply_collisions(
+    world: &World,
+    bodies: &RigidBodySet,
+    colliders: &ColliderSet,
+    narrow_phase: &NarrowPhase,
+    contacts: &mut Contacts,
+) {
+    let mut impacts: Vec<(RigidBodyHandle, f32)> = Vec::new();
+    let mut touching = Vec::new();
+
+    for contact_pair in narrow_phase.contact_pairs() {
+        if !contact_pair.has_any_active_contact {
+            continue;
+        }
+
+        let pair = (contact_pair.pair.collider1, contact_pair.pair.collider2);
+        touching.push(pair);
+
+        // bodies resting against each other were damaged when they met
+        if contacts.touching.contains(&pair) {
+            continue;
+        }
+
+        let body1 = colliders.get(pair.0).unwrap().parent();
+        let body2 = colliders.get(pair.1).unwrap().parent();
+
+        let velocity = |body| contacts.velocities.get(&body)
+            .copied()
+            .unwrap_or_else(|| *bodies.get(body).unwrap().linvel());
+
+        let velocity = velocity(body1) - velocity(body2);
+
+        // only the speed along the contact normal hurts, not grazing
+        let speed = match contact_pair.manifolds.first() {
+            Some(manifold) => velocity.dot(&manifold.data.normal).abs(),
+            None           => velocity.norm(),
+        };
+
+        let damage = impact_damage(speed);
+
+        if damage > 0.0 {
+            impacts.push((body1, damage));
+            impacts.push((body2, damage));
+        }
+    }
+
+    contacts.touching = touching;
+    contacts.velocities = bodies.iter()
+        .map(|(handle, body)| (handle, *body.linvel()))
+        .collect();
+
+    if impacts.is_empty() {
+        return;
+    }
+
+    let query = world.query::<( &mut RigidBodyHandle, &mut Stats )>();
+
+    for (rigid_body, stats) in query {
+        for (body, amount) in impacts.iter() {
+            if body == rigid_body {
+                damage(stats, *amount);
             }
+        }
+    }
+}
 
-            body.apply_torque(delta_axis * delta_angle * 50.0, true);
+// Beam interaction, despawn and model sync of all drones, the part of the
+// drone control that doesn't depend on the input
+pub fn update(
+    world: &World,
+    bodies: &mut RigidBodySet,
+    god_mode_enabled: bool,
+    dt: f32,
+    to_exile: &mut ToExile,
+) {
+    let mut players = Vec::new();
+
+    let query = world.query::<( &mut RigidBodyHandle, &mut Stats )>();
+
+    for (rigid_body, stats) in query {
+        if stats.is_player {
+            players.push(bodies.get(*rigid_body).unwrap().position().translation.vector);
+        }
+    }
+
+    // capturers hold the middle of the medium zone of the nearest beam
+    let mut beams = Vec::new();
+
+    let query = world.query::<( &mut RigidBodyHandle, &mut beam::Stats )>();
 
-            // make camera following the player
-            camera.target = Point3::new(position.x, position.y, position.z);
+    for (rigid_body, beam_stats) in query {
+        beams.push((
+            bodies.get(*rigid_body).unwrap().position().translation.vector,
+            (beam_stats.radius_near + beam_stats.radius_medium) / 2.0,
+        ));
+    }
 
-            if input.is_action_hold(Action::Strike) & (stats.strike_charge < stats.charge)  {
-                stats.strike_charge = stats.strike_charge + D_STRIKE_CHARGE;
-            };
+    // Query drone entities
+    let query = world.query::<(
+        &Entity, &mut Transform, &mut RigidBodyHandle, &mut Stats, &mut physics::Forces
+    )>();
 
-            if input.is_action_deactivated(Action::Strike) {
-                body.apply_impulse(fwd * stats.strike_charge * 2.0, true);
-                stats.charge = stats.charge - stats.strike_charge;
-                stats.strike_charge = 0.0;
-            };
+    for (entity, transform, rigid_body, stats, forces) in query {
 
-            stats.charge = stats.charge.min(MAX_CHARGE);
-            stats.strike_charge = stats.strike_charge.min(stats.charge);
+        let body = bodies.get_mut(*rigid_body).unwrap();
+        let position = body.position().translation;
+
+        // bots chase the nearest player
+        let target = if stats.capturer {
+            beams.iter()
+                .min_by(|(a, _), (b, _)| {
+                    let a = (*a - position.vector).norm();
+                    let b = (*b - position.vector).norm();
+                    a.partial_cmp(&b).unwrap()
+                })
+                .map(|(beam, hold)| holding_point(&position.vector, beam, *hold))
+        } else {
+            players.iter()
+                .min_by(|a, b| {
+                    let a = (*a - position.vector).norm();
+                    let b = (*b - position.vector).norm();
+                    a.partial_cmp(&b).unwrap()
+                })
+                .copied()
+        };
 
+        if let (false, Some(target)) = (stats.is_player, target) {
+            pursue(body, stats, &target, forces);
         }
 
         // interaction with beams
@@ -188,71 +409,221 @@ pub fn control(
             world.query::<(&mut RigidBodyHandle, &mut beam::Stats)>();
 
         for (beam_rigid_body, beam_stats) in beams_query {
-            let beam_body = bodies.get_mut(*beam_rigid_body).unwrap();
+            let beam_body = bodies.get(*beam_rigid_body).unwrap();
             let beam_position = beam_body.position().translation;
 
-            let distance = na::distance(
-                &na::Point3::new(position.x, position.y, position.z,),
-                &na::Point3::new(
-                    beam_position.x, beam_position.y, beam_position.x)
-            );
+            let distance = distance(&position.vector, &beam_position.vector);
 
-            if distance < beam_stats.radius_near {
-                stats.charge = stats.charge + D_CHARGE;
-                stats.health = stats.health - D_HEALTH;
-            } else if distance < beam_stats.radius_medium {
-                stats.charge = stats.charge + D_CHARGE / 10.0;
-            } else if distance > beam_stats.radius_far {
-                stats.health = stats.health - D_HEALTH;
-            }
-
-            stats.dist_to_beam = distance;
+            interact(stats, distance, beam_stats);
         }
 
-        //god mode
-        if stats.is_player & settings.god_mode {
-            stats.health = 100.0;
-        }
+        god_mode(stats, god_mode_enabled);
+
+        stats.boost = (stats.boost - dt).max(0.0);
 
         // despawn
         if stats.health <= 0.0 {
             to_exile.entity_list.push(*entity);
         }
 
-        // apply translation to the model
-        transform.translate.x = position.x;
-        transform.translate.y = position.y;
-        transform.translate.z = position.z;
-
         stats.x = position.x;
         stats.y = position.y;
         stats.z = position.z;
 
-        //TO DO: rething dw1 and dw2 usage
-        let dw2 = UnitQuaternion::from_euler_angles(0.0, 0.0, PI/2.0);
-        let rot = rotation * dw2.inverse();
+        sync_transform(transform, body);
+    }
+}
 
-        // apply rotation to the model
-        transform.rotate = Quat::new(
-            rot.into_inner().w,
-            rot.into_inner().j,
-            rot.into_inner().k,
-            rot.into_inner().i,
-        );
+// Moves the drone model to its rigid body
+pub fn sync_transform(transform: &mut Transform, body: &RigidBody) {
+    let position = body.position().translation;
+
+    // apply translation to the model
+    transform.translate.x = position.x;
+    transform.translate.y = position.y;
+    transform.translate.z = position.z;
+
+    // the body is rotated as the model is drawn, so the hull of the model
+    // fits it
+    let rot = body.position().rotation.into_inner();
+
+    // apply rotation to the model
+    transform.rotate = Quat::new(rot.w, rot.i, rot.j, rot.k);
+}
+
+// drag force to limit acceleration
+pub fn drag(velo: &Vector3<f32>) -> Vector3<f32> {
+    let speed = (velo.dot(velo)).sqrt() - VELO_MIN;
+
+    if speed > 0.0 {
+        -(0.1*speed + 0.002 * speed.powf(2.0)) * velo.normalize()
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
     }
 }
 
-pub fn spawn(
-    world: &mut World,
-    assets: &mut Assets,
+// bots with aggression fly towards the target spending their charge
+pub fn pursue(
+    body: &mut RigidBody,
+    stats: &mut Stats,
+    target: &Vector3<f32>,
+    forces: &mut physics::Forces,
+) {
+    if stats.aggression <= 0.0 {
+        return;
+    }
+
+    let offset = target - body.position().translation.vector;
+
+    forces.thrust = Vector3::new(0.0, 0.0, 0.0);
+
+    if (stats.charge >= D_MOVE_CHARGE) & (offset.norm() > 0.0) {
+        forces.thrust = offset.normalize() * stats.aggression * boost_factor(stats);
+        body.apply_force(forces.thrust, true);
+        stats.charge = stats.charge - D_MOVE_CHARGE;
+    }
+
+    let velo = *body.linvel();
+    forces.drag = drag(&velo);
+    body.apply_force(forces.drag, true);
+}
+
+// Point at `hold` from the beam on the line to the drone, a drone flying to
+// it keeps that distance from the beam
+pub fn holding_point(
+    position: &Vector3<f32>,
+    beam_position: &Vector3<f32>,
+    hold: f32,
+) -> Vector3<f32> {
+    let offset = position - beam_position;
+
+    let direction = if offset.norm() > 0.0 {
+        offset.normalize()
+    } else {
+        Vector3::x()
+    };
+
+    beam_position + direction * hold
+}
+
+// thrust multiplier of a boosted drone
+pub fn boost_factor(stats: &Stats) -> f32 {
+    if stats.boost > 0.0 {
+        BOOST_FACTOR
+    } else {
+        1.0
+    }
+}
+
+// distance between a drone and a beam
+pub fn distance(position: &Vector3<f32>, beam_position: &Vector3<f32>) -> f32 {
+    na::distance(
+        &na::Point3::from(*position),
+        &na::Point3::from(*beam_position),
+    )
+}
+
+// reduces the health, the shield absorbs its portion of the damage
+pub fn damage(stats: &mut Stats, amount: f32) {
+    stats.health = stats.health - amount * (1.0 - stats.shield);
+}
+
+// damage of a collision with the relative speed `speed`
+pub fn impact_damage(speed: f32) -> f32 {
+    (speed - SAFE_IMPACT_SPEED).max(0.0) * D_IMPACT_HEALTH
+}
+
+// charging and damage caused by a beam at `distance`
+pub fn interact(stats: &mut Stats, distance: f32, beam_stats: &beam::Stats) {
+    if distance < beam_stats.radius_near {
+        stats.charge = stats.charge + D_CHARGE;
+        damage(stats, D_HEALTH);
+    } else if distance < beam_stats.radius_medium {
+        stats.charge = stats.charge + D_CHARGE / 10.0;
+    } else if distance > beam_stats.radius_far {
+        damage(stats, D_HEALTH);
+    }
+
+    stats.dist_to_beam = distance;
+}
+
+// accumulates the strike charge while the strike is held
+pub fn charge_strike(stats: &mut Stats) {
+    if stats.strike_charge < stats.charge {
+        stats.strike_charge = stats.strike_charge + D_STRIKE_CHARGE;
+    }
+}
+
+// spends the accumulated strike charge, returns the impulse magnitude
+pub fn release_strike(stats: &mut Stats) -> f32 {
+    let impulse = stats.strike_charge * STRIKE_IMPULSE;
+
+    stats.charge = stats.charge - stats.strike_charge;
+    stats.strike_charge = 0.0;
+
+    impulse
+}
+
+pub fn clamp_charge(stats: &mut Stats) {
+    stats.charge = stats.charge.min(MAX_CHARGE);
+    stats.strike_charge = stats.strike_charge.min(stats.charge);
+}
+
+pub fn god_mode(stats: &mut Stats, enabled: bool) {
+    if stats.is_player & enabled {
+        stats.health = 100.0;
+    }
+}
+
+// forward and side (left) unit vectors of a drone rotated by `rotation`
+pub fn axes(rotation: &UnitQuaternion<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let rotation_euler = rotation.euler_angles();
+
+    let fwd = Vector3::new(
+        -rotation_euler.2.sin() * rotation_euler.1.cos(),
+        rotation_euler.1.sin(),
+        -rotation_euler.2.cos() * rotation_euler.1.cos(),
+    );
+
+    let side = Vector3::new(
+        (-PI/2.0 + rotation_euler.2).sin(),
+        0.0,
+        (-PI/2.0 + rotation_euler.2).cos()
+    );
+
+    (fwd, side)
+}
+
+// The controls are computed with the axes of the model cycled, x of the
+// model is y of the controls, y is z and z is x
+fn to_model(v: &Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(v.y, v.z, v.x)
+}
+
+fn from_model(v: &Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(v.z, v.x, v.y)
+}
+
+// drone body rotation in the axes of the controls, compensated for the model
+// orientation
+pub fn rotation(body: &RigidBody) -> UnitQuaternion<f32> {
+    let rot = body.position().rotation.into_inner();
+    let rot = UnitQuaternion::new_unchecked(
+        na::Quaternion::from_parts(rot.w, from_model(&rot.imag()))
+    );
+
+    //TO DO: rethink dw1 usage
+    let dw1 = UnitQuaternion::from_euler_angles(0.0, 0.0, -PI/2.0);
+    rot * dw1.inverse()
+}
+
+// Adds the drone rigid body and collider to the physics sets
+pub fn insert_body(
     bodies: &mut RigidBodySet,
     colliders: &mut ColliderSet,
+    hull: Option<&[na::Point3<f32>]>,
     position: Point3,
-    is_player: bool,
-) {
-    let texture = assets.register("drone::texture");
-    let mesh = assets.register("drone::mesh");
-
+) -> RigidBodyHandle {
     let rigid_body = RigidBodyBuilder::new(BodyStatus::Dynamic)
         .translation(position.x, position.y, position.z)
         .angular_damping(40.0)
@@ -260,14 +631,30 @@ pub fn spawn(
         .linear_damping(0.0)
         .build();
 
-    let collider = ColliderBuilder::ball(1.0)
-        .density(0.02)
+    let collider = hull::collider(hull, MODEL_SCALE, 1.0, Some(MASS))
         .build();
 
     let body_handle = bodies.insert(rigid_body);
 
     colliders.insert(collider, body_handle, bodies);
 
+    body_handle
+}
+
+pub fn spawn(
+    world: &mut World,
+    assets: &mut Assets,
+    bodies: &mut RigidBodySet,
+    colliders: &mut ColliderSet,
+    hulls: &hull::Hulls,
+    position: Point3,
+    stats: Stats,
+) -> RigidBodyHandle {
+    let texture = assets.register("drone::texture");
+    let mesh = assets.register("drone::mesh");
+
+    let body_handle = insert_body(bodies, colliders, hulls.get("drone"), position);
+
     world.spawn(Some((
         Model::from(mesh),
         Material {
@@ -276,35 +663,371 @@ pub fn spawn(
         },
         Transform {
             translate: Vec3::new(position.x, position.y, position.z),
-            scale: Vec3::new(1.18, 1.18, 1.18),
+            scale: Vec3::new(MODEL_SCALE, MODEL_SCALE, MODEL_SCALE),
             ..Default::default()
         },
         body_handle,
-        Stats{ is_player, ..Default::default() },
+        stats,
+        ability::Abilities::default(),
+        physics::Forces::default(),
         Pipeline::default(),
     )));
+
+    body_handle
 }
 
-pub fn exile(
-    world: Const<World>,
-    to_exile: Const<ToExile>,
-    mut bodies: Mut<RigidBodySet>,
-    mut colliders: Mut<ColliderSet>,
-    mut joints: Mut<JointSet>,
-) {
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Query drone entities
-    let query = world.query::<(
-        &Entity, &mut RigidBodyHandle, &mut Stats
-    )>();
+    use proptest::prelude::*;
+
+    use rapier3d::{
+        dynamics::{ CCDSolver, JointSet, },
+        geometry::{ BroadPhase, },
+        pipeline::{ QueryPipeline, },
+    };
+
+    fn player() -> Stats {
+        Stats { is_player: true, ..Default::default() }
+    }
+
+    #[test]
+    fn distance_uses_all_coordinates() {
+        let position = Vector3::new(1.0, 2.0, 10.0);
+
+        // a drone at the beam must be at zero distance, using x for z
+        // would place it 9 units away
+        assert_eq!(distance(&position, &position), 0.0);
+        assert_eq!(
+            distance(&Vector3::new(0.0, 0.0, 0.0), &Vector3::new(3.0, 0.0, 4.0)),
+            5.0
+        );
+    }
+
+    #[test]
+    fn update_measures_beams_off_the_axes() {
+        let mut world = World::new();
+        let mut bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+        let mut to_exile = ToExile::default();
+
+        // reading x for z would place the drone 50 units away, outside the
+        // near zone
+        let beam_body = beam::insert_body(
+            &mut bodies, &mut colliders, None, Point3::new(10.0, 0.0, 60.0),
+        );
+        world.spawn(Some((beam_body, beam::Stats::default())));
+
+        let drone_body = insert_body(
+            &mut bodies, &mut colliders, None, Point3::new(10.0, 0.0, 61.0),
+        );
+        world.spawn(Some((
+            Transform::default(), drone_body, player(), physics::Forces::default(),
+        )));
+
+        update(&world, &mut bodies, false, 1.0 / 60.0, &mut to_exile);
+
+        let drones: Vec<(f32, f32)> = world.query::<( &Stats, )>()
+            .map(|(stats,)| (stats.dist_to_beam, stats.charge))
+            .collect();
+
+        assert_eq!(drones.len(), 1);
+        assert!((drones[0].0 - 1.0).abs() < 1e-4);
+        assert_eq!(drones[0].1, D_CHARGE);
+    }
+
+    #[test]
+    fn capturers_hold_their_distance() {
+        let beam_position = Vector3::new(0.0, 10.0, 0.0);
+
+        // from outside and from inside the drone is sent to the same ring
+        let outside = holding_point(&Vector3::new(0.0, 10.0, 200.0), &beam_position, 50.0);
+        let inside = holding_point(&Vector3::new(0.0, 10.0, 5.0), &beam_position, 50.0);
+
+        assert_eq!(outside, Vector3::new(0.0, 10.0, 50.0));
+        assert_eq!(inside, outside);
+        assert_eq!((holding_point(&beam_position, &beam_position, 50.0) - beam_position).norm(), 50.0);
+    }
+
+    #[test]
+    fn near_zone_charges_and_damages() {
+        let beam_stats = beam::Stats::default();
+        let mut stats = player();
+
+        interact(&mut stats, beam_stats.radius_near - 1.0, &beam_stats);
+
+        assert_eq!(stats.charge, D_CHARGE);
+        assert_eq!(stats.health, 100.0 - D_HEALTH);
+        assert_eq!(stats.dist_to_beam, beam_stats.radius_near - 1.0);
+    }
+
+    #[test]
+    fn medium_zone_charges_slowly_without_damage() {
+        let beam_stats = beam::Stats::default();
+        let mut stats = player();
+
+        interact(&mut stats, beam_stats.radius_medium - 1.0, &beam_stats);
+
+        assert_eq!(stats.charge, D_CHARGE / 10.0);
+        assert_eq!(stats.health, 100.0);
+    }
+
+    #[test]
+    fn between_medium_and_far_is_neutral() {
+        let beam_stats = beam::Stats::default();
+        let mut stats = player();
+
+        interact(&mut stats, beam_stats.radius_far, &beam_stats);
+
+        assert_eq!(stats.charge, 0.0);
+        assert_eq!(stats.health, 100.0);
+    }
+
+    #[test]
+    fn outside_far_zone_damages() {
+        let beam_stats = beam::Stats::default();
+        let mut stats = player();
 
-    for (entity, rigid_body, _) in query {
+        interact(&mut stats, beam_stats.radius_far + 1.0, &beam_stats);
 
-        for i in 0..to_exile.entity_list.len() {
-            if entity == &to_exile.entity_list[i] {
-                bodies.remove(*rigid_body, &mut colliders, &mut joints);
-                break;
+        assert_eq!(stats.charge, 0.0);
+        assert_eq!(stats.health, 100.0 - D_HEALTH);
+    }
+
+    #[test]
+    fn shield_absorbs_beam_damage() {
+        let beam_stats = beam::Stats::default();
+        let mut stats = Stats { shield: 1.0, ..player() };
+
+        interact(&mut stats, beam_stats.radius_far + 1.0, &beam_stats);
+
+        assert_eq!(stats.health, 100.0);
+    }
+
+    #[test]
+    fn shield_reduces_damage() {
+        let mut stats = Stats { shield: ability::SHIELD_ABSORB, ..player() };
+
+        damage(&mut stats, 10.0);
+
+        assert_eq!(stats.health, 100.0 - 10.0 * (1.0 - ability::SHIELD_ABSORB));
+    }
+
+    #[test]
+    fn slow_collisions_are_harmless() {
+        assert_eq!(impact_damage(SAFE_IMPACT_SPEED), 0.0);
+        assert!(impact_damage(SAFE_IMPACT_SPEED * 2.0) > 0.0);
+    }
+
+    #[test]
+    fn an_impact_damages_once() {
+        let mut world = World::new();
+        let mut pipeline = physics::Pipeline::default();
+        let mut bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+        let mut joints = JointSet::new();
+        let mut broad_phase = BroadPhase::new();
+        let mut narrow_phase = NarrowPhase::new();
+        let mut ccd_solver = CCDSolver::new();
+        let mut query_pipeline = QueryPipeline::new();
+        let mut contacts = Contacts::default();
+
+        // two drones flying head-on into each other, staying in contact after
+        for (x, speed) in [(-3.0, 10.0), (3.0, -10.0)].iter() {
+            let body = insert_body(&mut bodies, &mut colliders, None, Point3::new(*x, 0.0, 0.0));
+            bodies.get_mut(body).unwrap().set_linvel(Vector3::new(*speed, 0.0, 0.0), true);
+            world.spawn(Some((body, player())));
+        }
+
+        let mut hits = 0;
+        let mut health = 100.0;
+
+        for _ in 0..120 {
+            pipeline.run(
+                1.0 / 60.0,
+                &mut bodies,
+                &mut colliders,
+                &mut joints,
+                &mut broad_phase,
+                &mut narrow_phase,
+                &mut ccd_solver,
+                &mut query_pipeline,
+            );
+            apply_collisions(&world, &bodies, &colliders, &narrow_phase, &mut contacts);
+
+            let healths: Vec<f32> = world.query::<( &Stats, )>()
+                .map(|(stats,)| stats.health)
+                .collect();
+
+            assert_eq!(healths[0], healths[1]);
+
+            if healths[0] != health {
+                hits = hits + 1;
+                health = healths[0];
+            }
+        }
+
+        // the damage of the speed before the solver stopped the drones
+        assert_eq!(hits, 1);
+        assert!((health - (100.0 - impact_damage(20.0))).abs() < 1e-3);
+    }
+
+    #[test]
+    fn the_hull_turns_with_the_model() {
+        let points = hull::read_points("assets/drone/drone.gltf").unwrap();
+
+        let mut pipeline = physics::Pipeline::default();
+        let mut bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+        let mut joints = JointSet::new();
+        let mut broad_phase = BroadPhase::new();
+        let mut narrow_phase = NarrowPhase::new();
+        let mut ccd_solver = CCDSolver::new();
+        let mut query_pipeline = QueryPipeline::new();
+
+        let handle = insert_body(
+            &mut bodies, &mut colliders, Some(&points), Point3::new(1.0, 2.0, 3.0),
+        );
+
+        let intent = Intent { xz_angle: 1.0, y_angle: 0.3, ..Default::default() };
+        let mut stats = player();
+        let mut forces = physics::Forces::default();
+
+        for _ in 0..300 {
+            fly(bodies.get_mut(handle).unwrap(), &mut stats, &mut forces, &intent, None);
+            pipeline.run(
+                1.0 / 60.0,
+                &mut bodies,
+                &mut colliders,
+                &mut joints,
+                &mut broad_phase,
+                &mut narrow_phase,
+                &mut ccd_solver,
+                &mut query_pipeline,
+            );
+        }
+
+        let body = bodies.get(handle).unwrap();
+
+        // the controls still turn the drone to the heading of the camera
+        let target = UnitQuaternion::from_euler_angles(
+            0.0, -intent.xz_angle, PI/2.0 - intent.y_angle,
+        );
+        assert!(rotation(body).angle_to(&target) < 0.05);
+
+        // and the model is drawn where the collider has its points
+        let collider = colliders.get(body.colliders()[0]).unwrap();
+        let mut transform = Transform::default();
+        sync_transform(&mut transform, body);
+
+        for point in points.iter() {
+            let drawn = transform.rotate * (Vec3::new(point.x, point.y, point.z) * MODEL_SCALE)
+                + transform.translate;
+            let hull = collider.position() * (point * MODEL_SCALE);
+
+            assert!((Vector3::new(drawn.x, drawn.y, drawn.z) - hull.coords).norm() < 1e-3);
+        }
+
+        let axis = Vector3::new(1.0, 2.0, 3.0);
+        assert_eq!(from_model(&to_model(&axis)), axis);
+    }
+
+    #[test]
+    fn strike_charge_is_capped_by_charge() {
+        let mut stats = Stats { charge: 1.0, ..player() };
+
+        for _ in 0..10 {
+            charge_strike(&mut stats);
+        }
+        clamp_charge(&mut stats);
+
+        assert_eq!(stats.strike_charge, 1.0);
+    }
+
+    #[test]
+    fn strike_release_spends_charge() {
+        let mut stats = Stats { charge: 10.0, strike_charge: 4.0, ..player() };
+
+        let impulse = release_strike(&mut stats);
+
+        assert_eq!(impulse, 4.0 * STRIKE_IMPULSE);
+        assert_eq!(stats.charge, 6.0);
+        assert_eq!(stats.strike_charge, 0.0);
+    }
+
+    #[test]
+    fn charge_is_clamped() {
+        let mut stats = Stats { charge: MAX_CHARGE + 10.0, ..player() };
+
+        clamp_charge(&mut stats);
+
+        assert_eq!(stats.charge, MAX_CHARGE);
+    }
+
+    #[test]
+    fn god_mode_restores_player_health_only() {
+        let mut immortal = Stats { health: 10.0, ..player() };
+        let mut mortal = Stats { health: 10.0, ..player() };
+        let mut bot = Stats { health: 10.0, ..Default::default() };
+
+        god_mode(&mut immortal, true);
+        god_mode(&mut mortal, false);
+        god_mode(&mut bot, true);
+
+        assert_eq!(immortal.health, 100.0);
+        assert_eq!(mortal.health, 10.0);
+        assert_eq!(bot.health, 10.0);
+    }
+
+    proptest! {
+        #[test]
+        fn distance_is_symmetric_and_non_negative(
+            a in prop::array::uniform3(-1000.0f32..1000.0),
+            b in prop::array::uniform3(-1000.0f32..1000.0),
+        ) {
+            let a = Vector3::new(a[0], a[1], a[2]);
+            let b = Vector3::new(b[0], b[1], b[2]);
+
+            prop_assert!(distance(&a, &b) >= 0.0);
+            prop_assert_eq!(distance(&a, &b), distance(&b, &a));
+            prop_assert!((distance(&a, &b) - (a - b).norm()).abs() < 1e-3);
+        }
+
+        #[test]
+        fn beams_never_heal(distance in 0.0f32..1000.0, health in 0.0f32..100.0) {
+            let beam_stats = beam::Stats::default();
+            let mut stats = Stats { health, ..Default::default() };
+
+            interact(&mut stats, distance, &beam_stats);
+
+            prop_assert!(stats.health <= health);
+            prop_assert!(stats.charge >= 0.0);
+        }
+
+        #[test]
+        fn charge_stays_in_range(
+            charge in 0.0f32..200.0,
+            frames in 0usize..500,
+            distance in 0.0f32..200.0,
+        ) {
+            let beam_stats = beam::Stats::default();
+            let mut stats = Stats { charge, ..player() };
+
+            for _ in 0..frames {
+                interact(&mut stats, distance, &beam_stats);
+                charge_strike(&mut stats);
+                clamp_charge(&mut stats);
+
+                prop_assert!(stats.charge <= MAX_CHARGE);
+                prop_assert!(stats.strike_charge <= stats.charge);
             }
+
+            let before = stats.charge;
+            release_strike(&mut stats);
+
+            prop_assert!(stats.charge >= 0.0);
+            prop_assert!(stats.charge <= before);
         }
     }
 }
@@ -0,0 +1,387 @@
+use super::{
+    capture, drone, lobby, net, settings::GameMode, survival,
+    Credits, Loading, Lobby, Main, Online, Results, Title,
+};
+
+use dotrix::ecs::{ Mut, Const, Entity, };
+use dotrix::{ Frame, Window, State, };
+use dotrix::services::{ World, };
+use dotrix::overlay::Overlay;
+
+use dotrix::egui::{
+    self,
+    Egui,
+};
+
+// seconds a finished match stays on screen before the results
+const RESULTS_DELAY: f32 = 2.0;
+
+// Screens of the game, each is a state alone on the stack. Pause is pushed
+// over the in-game screens and popped when the game resumes.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Screen {
+    Title,
+    Credits,
+    Lobby,
+    Loading,
+    // offline match
+    InGame,
+    // match on a server
+    Online,
+    Results,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Event {
+    Play,
+    ShowCredits,
+    Back,
+    // start the match chosen in the lobby
+    Start,
+    Loaded,
+    Joined,
+    Finished,
+    // the same match again
+    Restart,
+    // to the lobby to choose another match
+    NewMatch,
+    // to the title screen
+    Leave,
+}
+
+// Screen the event leads to, None if the event means nothing there
+pub fn transition(screen: Screen, event: Event) -> Option<Screen> {
+    match (screen, event) {
+        (Screen::Title,   Event::Play)        => Some(Screen::Lobby),
+        (Screen::Title,   Event::ShowCredits) => Some(Screen::Credits),
+        (Screen::Credits, Event::Back)        => Some(Screen::Title),
+        (Screen::Lobby,   Event::Start)       => Some(Screen::Loading),
+        (Screen::Lobby,   Event::Back)        => Some(Screen::Title),
+        (Screen::Loading, Event::Loaded)      => Some(Screen::InGame),
+        (Screen::Loading, Event::Joined)      => Some(Screen::Online),
+        (Screen::InGame,  Event::Finished)    => Some(Screen::Results),
+        (Screen::InGame,  Event::Restart)
+        | (Screen::Online, Event::Restart)
+        | (Screen::Results, Event::Restart)   => Some(Screen::Loading),
+        (Screen::InGame,  Event::NewMatch)
+        | (Screen::Online, Event::NewMatch)
+        | (Screen::Results, Event::NewMatch)  => Some(Screen::Lobby),
+        (Screen::InGame,  Event::Leave)
+        | (Screen::Online, Event::Leave)
+        | (Screen::Results, Event::Leave)     => Some(Screen::Title),
+        _                                     => None,
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Outcome {
+    pub victory: bool,
+    pub summary: String,
+}
+
+pub struct Flow {
+    screen: Screen,
+    // event to handle at the end of the frame
+    pending: Option<Event>,
+    // result of the last match
+    pub outcome: Option<Outcome>,
+    // seconds left before the results of a finished match
+    ending: Option<f32>,
+}
+
+impl Default for Flow {
+    fn default() -> Self {
+        Self {
+            screen: Screen::Title,
+            pending: None,
+            outcome: None,
+            ending: None,
+        }
+    }
+}
+
+impl Flow {
+    pub fn screen(&self) -> Screen {
+        self.screen
+    }
+
+    // The first event of a frame wins, e.g. a restart clicked in the same
+    // frame the match finished
+    pub fn send(&mut self, event: Event) {
+        if self.pending.is_none() {
+            self.pending = Some(event);
+        }
+    }
+}
+
+// Moves to the screen of the pending event, runs after the other systems
+pub fn apply(
+    mut flow: Mut<Flow>,
+    mut client: Mut<net::Client>,
+    mut game: Mut<lobby::Match>,
+    mut state: Mut<State>,
+) {
+    let event = match flow.pending.take() {
+        Some(event) => event,
+        None        => return,
+    };
+
+    let screen = match transition(flow.screen, event) {
+        Some(screen) => screen,
+        None         => {
+            println!("Ignoring {:?} on the {:?} screen", event, flow.screen);
+            return;
+        },
+    };
+
+    match screen {
+        Screen::Loading => {
+            flow.outcome = None;
+            flow.ending = None;
+        },
+        // leave the server of an online match, then stop it if it was hosted
+        Screen::Title | Screen::Lobby => {
+            *client = net::Client::default();
+            game.stop_hosting();
+        },
+        _ => {},
+    }
+
+    flow.screen = screen;
+
+    while state.pop_any().is_some() {};
+
+    match screen {
+        Screen::Title   => state.push(Title {}),
+        Screen::Credits => state.push(Credits {}),
+        Screen::Lobby   => state.push(Lobby {}),
+        Screen::Loading => state.push(Loading {}),
+        Screen::InGame  => state.push(Main {}),
+        Screen::Online  => state.push(Online {}),
+        Screen::Results => state.push(Results {}),
+    }
+}
+
+// How the match ended, None while it goes on
+pub fn outcome(
+    mode: GameMode,
+    players: usize,
+    bots: Option<usize>,
+    wave: usize,
+    winner: Option<bool>,
+) -> Option<Outcome> {
+    let outcome = |victory, summary: String| Some(Outcome { victory, summary });
+
+    if let Some(is_player) = winner {
+        return match is_player {
+            true  => outcome(true, String::from("The beam is yours")),
+            false => outcome(false, String::from("A bot captured the beam")),
+        };
+    }
+
+    if players == 0 {
+        return match mode {
+            GameMode::Survival => outcome(false, format!("Eliminated in wave {}", wave)),
+            _                  => outcome(false, String::from("Eliminated")),
+        };
+    }
+
+    match (mode, bots) {
+        (GameMode::Classic, Some(0)) => outcome(true, String::from("All bots eliminated")),
+        _                            => None,
+    }
+}
+
+// Ends the offline match when it is won or lost
+pub fn referee(
+    world: Const<World>,
+    active: Const<lobby::Active>,
+    capture: Const<capture::Capture>,
+    survival: Const<survival::Survival>,
+    frame: Const<Frame>,
+    mut flow: Mut<Flow>,
+) {
+    if let Some(countdown) = flow.ending {
+        let countdown = countdown - 1.0 / frame.fps();
+        flow.ending = Some(countdown);
+
+        if countdown <= 0.0 {
+            flow.send(Event::Finished);
+        }
+        return;
+    }
+
+    let query = world.query::<( &Entity, &drone::Stats )>();
+
+    let mut players = 0;
+    let mut bots = 0;
+    let mut winner = None;
+
+    for (entity, stats) in query {
+        match stats.is_player {
+            true  => players = players + 1,
+            false => bots = bots + 1,
+        }
+
+        if capture.winner == Some(*entity) {
+            winner = Some(stats.is_player);
+        }
+    }
+
+    // a match started without bots can't be won by eliminating them
+    let bots = if active.bots > 0 { Some(bots) } else { None };
+    let winner = if active.mode == GameMode::KingOfTheBeam { winner } else { None };
+
+    if let Some(outcome) = outcome(active.mode, players, bots, survival.wave, winner) {
+        flow.outcome = Some(outcome);
+        flow.ending = Some(RESULTS_DELAY);
+    }
+}
+
+fn menu_window(title: &str) -> egui::containers::Window {
+    egui::containers::Window::new(title)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::new(0.0, 0.0))
+        .collapsible(false)
+        .resizable(false)
+        .default_width(200.0)
+}
+
+pub fn title(
+    overlay: Const<Overlay>,
+    mut flow: Mut<Flow>,
+    mut window: Mut<Window>,
+) {
+    window.set_cursor_grab(false);
+    window.set_cursor_visible(true);
+
+    let egui = overlay.get::<Egui>()
+        .expect("Renderer does not contain an Overlay instance");
+
+    menu_window("title")
+        .title_bar(false)
+        .show(&egui.ctx, |ui| {
+            ui.vertical_centered_justified(|ui| {
+                ui.heading("DRONE TARGET");
+
+                if ui.button("Play").clicked() {
+                    flow.send(Event::Play);
+                }
+
+                if ui.button("Credits").clicked() {
+                    flow.send(Event::ShowCredits);
+                }
+
+                if ui.button("Exit").clicked() {
+                    window.close();
+                }
+            });
+        });
+}
+
+pub fn credits(
+    overlay: Const<Overlay>,
+    mut flow: Mut<Flow>,
+    mut window: Mut<Window>,
+) {
+    window.set_cursor_grab(false);
+    window.set_cursor_visible(true);
+
+    let egui = overlay.get::<Egui>()
+        .expect("Renderer does not contain an Overlay instance");
+
+    menu_window("Credits").show(&egui.ctx, |ui| {
+        ui.vertical_centered_justified(|ui| {
+            ui.label("A demo game for the Dotrix engine by Lowenware");
+            ui.label("Physics by Rapier, user interface by egui");
+            ui.label("Skybox by Elyvisions, opengameart.org, CC-BY 3.0");
+
+            if ui.button("Back").clicked() {
+                flow.send(Event::Back);
+            }
+        });
+    });
+}
+
+pub fn results(
+    overlay: Const<Overlay>,
+    mut flow: Mut<Flow>,
+    mut window: Mut<Window>,
+) {
+    window.set_cursor_grab(false);
+    window.set_cursor_visible(true);
+
+    let egui = overlay.get::<Egui>()
+        .expect("Renderer does not contain an Overlay instance");
+
+    let (heading, summary) = match &flow.outcome {
+        Some(outcome) if outcome.victory => ("VICTORY", outcome.summary.clone()),
+        Some(outcome)                    => ("DEFEAT", outcome.summary.clone()),
+        None                             => ("GAME OVER", String::new()),
+    };
+
+    menu_window("results")
+        .title_bar(false)
+        .show(&egui.ctx, |ui| {
+            ui.vertical_centered_justified(|ui| {
+                ui.heading(heading);
+                ui.label(summary);
+
+                if ui.button("Play again").clicked() {
+                    flow.send(Event::Restart);
+                }
+
+                if ui.button("New match").clicked() {
+                    flow.send(Event::NewMatch);
+                }
+
+                if ui.button("Title screen").clicked() {
+                    flow.send(Event::Leave);
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_match_goes_around() {
+        let events = [
+            Event::Play, Event::Start, Event::Loaded, Event::Finished,
+            Event::Restart, Event::Loaded, Event::NewMatch, Event::Back,
+        ];
+
+        let screen = events.iter().fold(Screen::Title, |screen, event| {
+            transition(screen, *event).unwrap()
+        });
+
+        assert_eq!(screen, Screen::Title);
+    }
+
+    #[test]
+    fn events_out_of_place_are_ignored() {
+        // online matches don't finish and the title has nothing to restart
+        assert_eq!(transition(Screen::Online, Event::Finished), None);
+        assert_eq!(transition(Screen::Title, Event::Restart), None);
+        assert_eq!(transition(Screen::Loading, Event::Leave), None);
+    }
+
+    #[test]
+    fn outcomes() {
+        assert_eq!(outcome(GameMode::Classic, 1, Some(3), 0, None), None);
+        assert!(outcome(GameMode::Classic, 1, Some(0), 0, None).unwrap().victory);
+        // without bots from the start the match goes on
+        assert_eq!(outcome(GameMode::Classic, 1, None, 0, None), None);
+        assert!(!outcome(GameMode::Classic, 0, Some(3), 0, None).unwrap().victory);
+
+        let survival = outcome(GameMode::Survival, 0, Some(0), 4, None).unwrap();
+        assert!(!survival.victory);
+        assert!(survival.summary.contains('4'));
+        // survival waves never run out
+        assert_eq!(outcome(GameMode::Survival, 1, Some(0), 4, None), None);
+
+        assert!(outcome(GameMode::KingOfTheBeam, 1, Some(2), 0, Some(true)).unwrap().victory);
+        assert!(!outcome(GameMode::KingOfTheBeam, 1, Some(2), 0, Some(false)).unwrap().victory);
+    }
+}
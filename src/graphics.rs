@@ -0,0 +1,431 @@
+use super::{ settings::WindowMode, };
+
+use serde::{ Serialize, Deserialize, };
+
+use dotrix::{
+    Window,
+    sky::SkyBox,
+    services::{ World, },
+    math::{ Vec2i, Vec2u, },
+    window::{ Fullscreen, },
+};
+
+use dotrix::egui;
+
+// graphics options are saved next to the assets directory
+pub const GRAPHICS_FILE: &str = "graphics.ron";
+
+// skybox distance range
+const MIN_VIEW_RANGE: f32 = 200.0;
+const MAX_VIEW_RANGE: f32 = 2000.0;
+// pixels of the window top that must stay on a monitor to grab it
+const MIN_VISIBLE: i32 = 64;
+// resolutions offered when the monitor doesn't report its video modes
+const FALLBACK_RESOLUTIONS: [[u32; 2]; 4] = [
+    [1920, 1080],
+    [1600, 900],
+    [1280, 720],
+    [1024, 576],
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Graphics {
+    pub window_mode: WindowMode,
+    // inner size of the window, the video mode in the exclusive fullscreen
+    pub resolution: [u32; 2],
+    // index of the monitor used in the fullscreen modes
+    pub monitor: usize,
+    // name of the monitor, the indices change when monitors are plugged in
+    pub monitor_name: Option<String>,
+    // outer position of the window, it is centred without one
+    pub position: Option<[i32; 2]>,
+    pub vsync: bool,
+    // distance of the skybox
+    pub view_range: f32,
+}
+
+impl Default for Graphics {
+    fn default() -> Self {
+        Self {
+            window_mode: WindowMode::Windowed,
+            resolution: [1280, 720],
+            monitor: 0,
+            monitor_name: None,
+            position: None,
+            vsync: true,
+            view_range: 500.0,
+        }
+    }
+}
+
+impl Graphics {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path, e))?;
+
+        ron::from_str::<Self>(&data)
+            .map(|graphics| graphics.sanitise())
+            .map_err(|e| format!("Could not parse {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let data = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("Could not serialize the graphics options: {}", e))?;
+
+        std::fs::write(path, data)
+            .map_err(|e| format!("Could not write {}: {}", path, e))
+    }
+
+    // Brings the values of a hand edited file back into their ranges
+    fn sanitise(mut self) -> Self {
+        self.view_range = self.view_range.max(MIN_VIEW_RANGE).min(MAX_VIEW_RANGE);
+        self.resolution = [self.resolution[0].max(1), self.resolution[1].max(1)];
+        self
+    }
+
+    // options applied to the window rather than the scene
+    fn display(&self) -> (WindowMode, [u32; 2], usize, bool) {
+        (self.window_mode, self.resolution, self.monitor, self.vsync)
+    }
+}
+
+// Desktop area of a monitor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Area {
+    pub name: String,
+    pub position: [i32; 2],
+    pub size: [u32; 2],
+}
+
+impl Area {
+    fn contains(&self, point: [i32; 2]) -> bool {
+        point[0] >= self.position[0]
+            && point[1] >= self.position[1]
+            && point[0] < self.position[0] + self.size[0] as i32
+            && point[1] < self.position[1] + self.size[1] as i32
+    }
+}
+
+pub fn areas(window: &Window) -> Vec<Area> {
+    window.monitors().iter()
+        .map(|monitor| Area {
+            name: monitor.name.clone(),
+            position: [monitor.position.x, monitor.position.y],
+            size: [monitor.size.x, monitor.size.y],
+        })
+        .collect()
+}
+
+// Monitor showing the point
+pub fn monitor_at(areas: &[Area], point: [i32; 2]) -> Option<usize> {
+    areas.iter().position(|area| area.contains(point))
+}
+
+// Fits the saved placement to the attached monitors: a detached monitor is
+// replaced by the first one, the window is shrunk to the monitor and
+// centred when its top would be out of reach
+pub fn restore(graphics: &mut Graphics, areas: &[Area]) {
+    if areas.is_empty() {
+        graphics.monitor = 0;
+        graphics.position = None;
+        return;
+    }
+
+    let found = match &graphics.monitor_name {
+        Some(name) => areas.iter().position(|area| area.name == *name),
+        None       => Some(graphics.monitor).filter(|monitor| *monitor < areas.len()),
+    };
+
+    let area = match found {
+        Some(monitor) => {
+            graphics.monitor = monitor;
+            &areas[monitor]
+        },
+        None          => {
+            println!("The monitor of the last session is detached, using the first one");
+            graphics.monitor = 0;
+            graphics.position = None;
+            &areas[0]
+        },
+    };
+
+    graphics.monitor_name = Some(area.name.clone());
+    graphics.resolution = [
+        graphics.resolution[0].min(area.size[0]),
+        graphics.resolution[1].min(area.size[1]),
+    ];
+
+    if let Some([x, y]) = graphics.position {
+        let width = graphics.resolution[0] as i32;
+
+        let margin = MIN_VISIBLE.min(width);
+
+        // either end of the top strip is enough to drag the window back
+        let reachable = [x + margin, x + width - margin].iter()
+            .any(|end| area.contains([*end, y]) && area.contains([*end, y + MIN_VISIBLE]));
+
+        if !reachable {
+            graphics.position = None;
+        }
+    }
+}
+
+// Distinct resolutions, the largest first
+pub fn resolutions(sizes: &[[u32; 2]]) -> Vec<[u32; 2]> {
+    let mut resolutions = sizes.to_vec();
+
+    resolutions.sort_by(|a, b| (b[0] * b[1], b[0]).cmp(&(a[0] * a[1], a[0])));
+    resolutions.dedup();
+
+    if resolutions.is_empty() {
+        resolutions = FALLBACK_RESOLUTIONS.to_vec();
+    }
+
+    resolutions
+}
+
+// Resolutions of the video modes of the monitor
+fn monitor_resolutions(window: &Window, monitor: usize) -> Vec<[u32; 2]> {
+    let sizes: Vec<[u32; 2]> = window.monitors().iter()
+        .nth(monitor)
+        .map_or(Vec::new(), |monitor| monitor.video_modes.iter()
+            .map(|mode| [mode.resolution.x, mode.resolution.y])
+            .collect()
+        );
+
+    resolutions(&sizes)
+}
+
+// Applies the window mode, the resolution, the monitor and the vsync
+pub fn apply_display(graphics: &mut Graphics, window: &mut Window) {
+    // the monitor may have been detached since the options were saved
+    if graphics.monitor >= window.monitors().len() {
+        graphics.monitor = 0;
+    }
+
+    graphics.monitor_name = window.monitors().iter()
+        .nth(graphics.monitor)
+        .map(|monitor| monitor.name.clone());
+
+    window.set_vsync(graphics.vsync);
+
+    match graphics.window_mode {
+        WindowMode::Windowed => {
+            window.set_fullscreen(None);
+            window.set_inner_size(Vec2u::new(graphics.resolution[0], graphics.resolution[1]));
+
+            match graphics.position {
+                Some([x, y]) => window.set_outer_position(Vec2i::new(x, y)),
+                None         => {
+                    let area = areas(window).into_iter().nth(graphics.monitor);
+                    let (origin, size) = area.map_or(
+                        ([0, 0], [window.screen_size().x, window.screen_size().y]),
+                        |area| (area.position, area.size),
+                    );
+
+                    window.set_outer_position(Vec2i::new(
+                        origin[0] + (size[0] as i32 - window.outer_size().x as i32) / 2,
+                        origin[1] + (size[1] as i32 - window.outer_size().y as i32) / 2,
+                    ));
+                },
+            }
+        },
+        WindowMode::BorderlessFullscreen => {
+            window.set_fullscreen(Some(Fullscreen::Borderless(graphics.monitor)));
+        },
+        WindowMode::ExclusiveFullscreen => {
+            // the fastest video mode of the resolution
+            let mode = window.monitors().iter()
+                .nth(graphics.monitor)
+                .and_then(|monitor| monitor.video_modes.iter()
+                    .filter(|mode| mode.resolution.x == graphics.resolution[0]
+                        && mode.resolution.y == graphics.resolution[1])
+                    .max_by_key(|mode| mode.refresh_rate)
+                    .cloned()
+                );
+
+            match mode {
+                Some(mode) => window.set_fullscreen(Some(Fullscreen::Exclusive(mode))),
+                None       => {
+                    println!(
+                        "The monitor has no {}x{} video mode, using the borderless fullscreen",
+                        graphics.resolution[0], graphics.resolution[1],
+                    );
+                    window.set_fullscreen(Some(Fullscreen::Borderless(graphics.monitor)));
+                },
+            }
+        },
+    }
+}
+
+// Applies the skybox range, the field of view is fixed by the renderer
+pub fn apply_view(graphics: &Graphics, world: &World) {
+    for (skybox,) in world.query::<(&mut SkyBox,)>() {
+        skybox.view_range = graphics.view_range;
+    }
+}
+
+// Draws the options, returns the display and the view changes
+pub fn page(ui: &mut egui::Ui, graphics: &mut Graphics, window: &Window) -> (bool, bool) {
+    let before = graphics.clone();
+
+    let mode_label = match graphics.window_mode {
+        WindowMode::Windowed             => "Display: windowed",
+        WindowMode::BorderlessFullscreen => "Display: borderless fullscreen",
+        WindowMode::ExclusiveFullscreen  => "Display: exclusive fullscreen",
+    };
+
+    if ui.button(mode_label).clicked() {
+        graphics.window_mode = graphics.window_mode.next();
+    }
+
+    let monitors = window.monitors().len();
+
+    if monitors > 1 {
+        let name = window.monitors().iter()
+            .nth(graphics.monitor)
+            .map_or(String::from("detached"), |monitor| monitor.name.clone());
+
+        // the window is centred on the new monitor
+        if ui.button(format!("Monitor: {}", name)).clicked() {
+            graphics.monitor = (graphics.monitor + 1) % monitors;
+            graphics.position = None;
+        }
+    }
+
+    // the borderless fullscreen takes the desktop resolution
+    if graphics.window_mode != WindowMode::BorderlessFullscreen {
+        let resolutions = monitor_resolutions(window, graphics.monitor);
+        let [width, height] = graphics.resolution;
+
+        if ui.button(format!("Resolution: {}x{}", width, height)).clicked() {
+            let next = resolutions.iter()
+                .position(|resolution| *resolution == graphics.resolution)
+                .map_or(0, |i| (i + 1) % resolutions.len());
+
+            graphics.resolution = resolutions[next];
+        }
+    }
+
+    if ui.button(if graphics.vsync { "Vsync: on" } else { "Vsync: off" }).clicked() {
+        graphics.vsync = !graphics.vsync;
+    }
+
+    ui.add(
+        egui::Slider::new(&mut graphics.view_range, MIN_VIEW_RANGE..=MAX_VIEW_RANGE)
+            .text("View range")
+    );
+
+    (
+        graphics.display() != before.display(),
+        graphics.view_range != before.view_range,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolutions_are_distinct_and_largest_first() {
+        let sizes = [[1280, 720], [1920, 1080], [1280, 720], [1280, 1024]];
+
+        assert_eq!(resolutions(&sizes), vec![[1920, 1080], [1280, 1024], [1280, 720]]);
+        assert_eq!(resolutions(&[]), FALLBACK_RESOLUTIONS.to_vec());
+    }
+
+    #[test]
+    fn options_round_trip() {
+        let path = std::env::temp_dir().join("drone_target_graphics.ron");
+        let path = path.to_str().unwrap();
+
+        let graphics = Graphics {
+            window_mode: WindowMode::ExclusiveFullscreen,
+            resolution: [1920, 1080],
+            monitor: 1,
+            monitor_name: Some(String::from("DP-1")),
+            position: Some([-1900, 40]),
+            vsync: false,
+            view_range: 1000.0,
+        };
+
+        graphics.save(path).unwrap();
+        assert_eq!(Graphics::load(path).unwrap(), graphics);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn edited_files_are_kept_in_range() {
+        let graphics: Graphics = ron::from_str("(view_range: 0.0)").unwrap();
+        let graphics = graphics.sanitise();
+
+        assert_eq!(graphics.view_range, MIN_VIEW_RANGE);
+        // missing options keep their defaults
+        assert_eq!(graphics.resolution, Graphics::default().resolution);
+    }
+
+    fn monitors() -> Vec<Area> {
+        vec![
+            Area { name: String::from("DP-1"), position: [0, 0], size: [1920, 1080] },
+            Area { name: String::from("DP-2"), position: [1920, 0], size: [1280, 1024] },
+        ]
+    }
+
+    #[test]
+    fn windows_are_restored_on_their_monitor() {
+        let mut graphics = Graphics {
+            monitor: 0,
+            monitor_name: Some(String::from("DP-2")),
+            position: Some([2000, 100]),
+            ..Default::default()
+        };
+
+        restore(&mut graphics, &monitors());
+
+        assert_eq!(graphics.monitor, 1);
+        assert_eq!(graphics.position, Some([2000, 100]));
+        assert_eq!(monitor_at(&monitors(), [2000, 100]), Some(1));
+    }
+
+    #[test]
+    fn detached_monitors_are_replaced() {
+        let mut graphics = Graphics {
+            monitor: 2,
+            monitor_name: Some(String::from("HDMI-1")),
+            position: Some([3500, 100]),
+            resolution: [2560, 1440],
+            ..Default::default()
+        };
+
+        restore(&mut graphics, &monitors());
+
+        assert_eq!(graphics.monitor, 0);
+        assert_eq!(graphics.monitor_name, Some(String::from("DP-1")));
+        assert_eq!(graphics.position, None);
+        // larger windows are shrunk to the monitor
+        assert_eq!(graphics.resolution, [1920, 1080]);
+    }
+
+    #[test]
+    fn unreachable_windows_are_centred() {
+        let placed = |position| {
+            let mut graphics = Graphics {
+                monitor_name: Some(String::from("DP-1")),
+                position: Some(position),
+                ..Default::default()
+            };
+
+            restore(&mut graphics, &monitors());
+            graphics.position
+        };
+
+        // partly off the left edge is fine, the right end of the top is reachable
+        assert_eq!(placed([-1000, 10]), Some([-1000, 10]));
+        assert_eq!(placed([-1270, 10]), None);
+        // the title bar above the monitor
+        assert_eq!(placed([100, -20]), None);
+        assert_eq!(placed([100, 1070]), None);
+    }
+}
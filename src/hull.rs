@@ -0,0 +1,117 @@
+use rapier3d::{
+    geometry::{ ColliderBuilder, },
+    na::{ Point3, },
+};
+
+use std::collections::HashMap;
+
+// models the colliders are built from, by name and path
+pub const MODELS: [(&str, &str); 2] = [
+    ("drone", "assets/drone/drone.gltf"),
+    ("energy_beam", "assets/energy_beam/energy_beam.gltf"),
+];
+
+// Convex hull points of the glTF models, by the model name
+#[derive(Clone)]
+pub struct Hulls {
+    list: HashMap<String, Vec<Point3<f32>>>,
+}
+
+impl Default for Hulls {
+    fn default() -> Self {
+        Self {
+            list: HashMap::new(),
+        }
+    }
+}
+
+impl Hulls {
+    // Reads the vertices of the model at `path` registering them as `name`
+    pub fn load(&mut self, name: &str, path: &str) -> Result<(), String> {
+        let points = read_points(path)?;
+
+        if points.len() < 4 {
+            return Err(format!("Not enough vertices in {} to build a hull", path));
+        }
+
+        self.list.insert(String::from(name), points);
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[Point3<f32>]> {
+        self.list.get(name).map(|points| points.as_slice())
+    }
+}
+
+// Vertex positions of all meshes in the glTF file, node transforms are
+// ignored as the models are exported with the single root node
+pub fn read_points(path: &str) -> Result<Vec<Point3<f32>>, String> {
+    let (document, buffers, _) = gltf::import(path)
+        .map_err(|e| format!("Could not import {}: {}", path, e))?;
+
+    let mut points = Vec::new();
+
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            if let Some(positions) = reader.read_positions() {
+                points.extend(positions.map(|p| Point3::new(p[0], p[1], p[2])));
+            }
+        }
+    }
+
+    Ok(points)
+}
+
+// Collider of the model with the given scale, a ball of `radius` is used when
+// there is no hull
+pub fn collider(
+    hull: Option<&[Point3<f32>]>,
+    scale: f32,
+    radius: f32,
+    mass: Option<f32>,
+) -> ColliderBuilder {
+    let builder = hull
+        .and_then(|points| {
+            let scaled: Vec<Point3<f32>> = points.iter().map(|p| p * scale).collect();
+            ColliderBuilder::convex_hull(&scaled)
+        })
+        .unwrap_or_else(|| ColliderBuilder::ball(radius));
+
+    // keep the mass independent of the shape, so handling doesn't change
+    // with the model
+    let volume = builder.shape.mass_properties(1.0).mass();
+
+    match mass {
+        Some(mass) if volume > 0.0 => builder.density(mass / volume),
+        _                          => builder,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_hulls_fit_the_models() {
+        for path in &["assets/drone/drone.gltf", "assets/energy_beam/energy_beam.gltf"] {
+            let points = read_points(path).unwrap();
+
+            assert!(points.len() >= 4);
+            assert!(ColliderBuilder::convex_hull(&points).is_some());
+        }
+    }
+
+    #[test]
+    fn collider_keeps_the_mass() {
+        let points = read_points("assets/drone/drone.gltf").unwrap();
+
+        let hull = collider(Some(&points), 1.18, 1.0, Some(0.1)).build();
+        let ball = collider(None, 1.18, 1.0, Some(0.1)).build();
+
+        assert!((hull.mass_properties().mass() - 0.1).abs() < 1e-4);
+        assert!((ball.mass_properties().mass() - 0.1).abs() < 1e-4);
+    }
+}
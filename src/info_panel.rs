@@ -1,4 +1,4 @@
-use super::{ drone::Stats, settings, Pause };
+use super::{ ability, drone::Stats, local, lobby, settings, survival, Pause };
 
 use dotrix::ecs::{ Const, Entity };
 use dotrix::services::{ World };
@@ -14,8 +14,11 @@ pub fn update(
     world: Const<World>,
     overlay: Const<Overlay>,
     settings: Const<settings::Settings>,
+    active: Const<lobby::Active>,
     frame: Const<Frame>,
     state: Const<State>,
+    survival: Const<survival::Survival>,
+    local: Const<local::Local>,
 ) {
     let info_ui_frame = egui::containers::Frame{
         fill: egui::Color32::from_black_alpha(192),
@@ -24,14 +27,27 @@ pub fn update(
         ..Default::default()
     };
 
-    // default value
-    let mut health        = 0;
-    let mut charge        = 0;
-    let mut strike_charge = 0;
-
     let egui = overlay.get::<Egui>()
         .expect("Renderer does not contain an Overlay instance");
 
+    // health, charge and strike charge of every local player, zero when the
+    // player is eliminated
+    let mut status = [[0u8; 3]; local::MAX_PLAYERS];
+
+    // abilities of every local player to display their state in the status bar
+    let mut abilities = [None; local::MAX_PLAYERS];
+
+    for (stats, player_abilities) in world.query::<( &Stats, &ability::Abilities )>() {
+        if stats.is_player & (stats.slot < local::MAX_PLAYERS) {
+            status[stats.slot] = [
+                stats.health as u8,
+                stats.charge as u8,
+                stats.strike_charge as u8,
+            ];
+            abilities[stats.slot] = Some(player_abilities.list);
+        }
+    }
+
     // Query all drones to display their stats
     let query = world.query::<( &Entity, &Stats )>();
 
@@ -71,12 +87,6 @@ pub fn update(
                     .enable_scrolling(paused)
                     .show(ui, |ui|{
                         for (entity, stats) in query {
-                            if stats.is_player {
-                                health        = stats.health as u8;
-                                charge        = stats.charge as u8;
-                                strike_charge = stats.strike_charge as u8;
-                            }
-
                             let label = if stats.is_player{
                                 format!("{:?} - player", entity)
                             } else {
@@ -108,55 +118,106 @@ pub fn update(
             });
     }
 
-    // draw the status bar
+    // draw the status bar of every player in its own corner of the screen
     let margin = 8.0;
-
-    egui::containers::Window::new("status_bar")
-        .anchor(egui::Align2::RIGHT_BOTTOM, egui::Vec2::new(-margin, -margin))
-        .collapsible(false)
-        .title_bar(false)
-        .resizable(false)
-        .frame(info_ui_frame)
-        .show(&egui.ctx, |ui| {
-            egui::Grid::new("status_bar_grid")
-                .max_col_width(75.0)
-                .show(ui, |ui| {
-                ui.vertical_centered_justified(|ui| {
-                    ui.add(
-                        egui::Label::new("HEALTH")
+    let screen = egui.ctx.input().screen_rect();
+    let areas = local::hud_areas(local.players, screen);
+
+    for (slot, area) in areas.iter().enumerate() {
+        let [health, charge, strike_charge] = status[slot];
+        let offset = area.right_bottom() - screen.right_bottom();
+
+        let title = if slot == 0 {
+            String::from("status_bar")
+        } else {
+            format!("status_bar_{}", slot)
+        };
+
+        egui::containers::Window::new(title)
+            .anchor(egui::Align2::RIGHT_BOTTOM, offset - egui::Vec2::new(margin, margin))
+            .collapsible(false)
+            .title_bar(false)
+            .resizable(false)
+            .frame(info_ui_frame)
+            .show(&egui.ctx, |ui| {
+                egui::Grid::new(format!("status_bar_grid_{}", slot))
+                    .max_col_width(75.0)
+                    .show(ui, |ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.add(
+                            egui::Label::new("HEALTH")
+                                .text_color(egui::Color32::LIGHT_GRAY)
+                                .strong()
+                        );
+                        ui.add(egui::Label::new(format!("{number:0>width$}", number = health, width = 3))
                             .text_color(egui::Color32::LIGHT_GRAY)
-                            .strong()
-                    );
-                    ui.add(egui::Label::new(format!("{number:0>width$}", number = health, width = 3))
-                        .text_color(egui::Color32::LIGHT_GRAY)
-                        .heading()
-                    );
-                });
+                            .heading()
+                        );
+                    });
 
-                ui.vertical_centered_justified(|ui| {
-                    ui.add(
-                        egui::Label::new("CHARGE")
+                    ui.vertical_centered_justified(|ui| {
+                        ui.add(
+                            egui::Label::new("CHARGE")
+                                .text_color(egui::Color32::LIGHT_GRAY)
+                                .strong()
+                        );
+                        ui.add(egui::Label::new(format!("{number:0>width$}", number = charge, width = 3))
                             .text_color(egui::Color32::LIGHT_GRAY)
-                            .strong()
-                    );
-                    ui.add(egui::Label::new(format!("{number:0>width$}", number = charge, width = 3))
-                        .text_color(egui::Color32::LIGHT_GRAY)
-                        .heading()
-                    );
-                });
+                            .heading()
+                        );
+                    });
 
-                ui.vertical_centered_justified(|ui| {
-                    ui.add(
-                        egui::Label::new("STRIKE")
+                    if active.mode == settings::GameMode::Survival {
+                        ui.vertical_centered_justified(|ui| {
+                            ui.add(
+                                egui::Label::new("WAVE")
+                                    .text_color(egui::Color32::LIGHT_GRAY)
+                                    .strong()
+                            );
+                            ui.add(egui::Label::new(format!("{number:0>width$}", number = survival.wave, width = 3))
+                                .text_color(egui::Color32::LIGHT_GRAY)
+                                .heading()
+                            );
+                        });
+                    }
+
+                    ui.vertical_centered_justified(|ui| {
+                        ui.add(
+                            egui::Label::new("STRIKE")
+                                .text_color(egui::Color32::LIGHT_GRAY)
+                                .strong()
+                        );
+                        ui.add(egui::Label::new(format!("{number:0>width$}", number = strike_charge, width = 3))
                             .text_color(egui::Color32::LIGHT_GRAY)
-                            .strong()
-                    );
-                    ui.add(egui::Label::new(format!("{number:0>width$}", number = strike_charge, width = 3))
-                        .text_color(egui::Color32::LIGHT_GRAY)
-                        .heading()
-                    );
+                            .heading()
+                        );
+                    });
                 });
-            });
-        });
 
+                if let Some(abilities) = abilities[slot] {
+                    egui::Grid::new(format!("abilities_grid_{}", slot))
+                        .max_col_width(56.0)
+                        .show(ui, |ui| {
+                        for ability in abilities.iter() {
+                            let (state, color) = if ability.is_active() {
+                                (String::from("ON"), egui::Color32::GREEN)
+                            } else if ability.is_ready() {
+                                (String::from("READY"), egui::Color32::LIGHT_GRAY)
+                            } else {
+                                (format!("{:.0}s", ability.ready_in.ceil()), egui::Color32::GRAY)
+                            };
+
+                            ui.vertical_centered_justified(|ui| {
+                                ui.add(
+                                    egui::Label::new(ability.kind.name())
+                                        .text_color(egui::Color32::LIGHT_GRAY)
+                                        .small()
+                                );
+                                ui.add(egui::Label::new(state).text_color(color).strong());
+                            });
+                        }
+                    });
+                }
+            });
+    }
 }
@@ -0,0 +1,121 @@
+pub mod physics;
+pub mod drone;
+pub mod beam;
+pub mod settings;
+pub mod info_panel;
+pub mod radar;
+pub mod view;
+pub mod targeting;
+pub mod survival;
+pub mod capture;
+pub mod pickup;
+pub mod ability;
+pub mod arena;
+pub mod hull;
+pub mod debug;
+pub mod local;
+pub mod net;
+pub mod server;
+pub mod prediction;
+pub mod wire;
+pub mod lobby;
+pub mod flow;
+pub mod dialog;
+pub mod graphics;
+
+use dotrix::{
+    Input,
+    input::{ ActionMapper, Button, KeyCode, Mapper, },
+    ecs::{ Entity, },
+};
+
+// States
+pub struct Pause {
+    handled: bool,
+    // confirmation of the menu action that ends the match
+    confirm: dialog::Dialog<settings::Confirm>,
+    // the graphics options are shown instead of the menu
+    graphics: bool,
+}
+
+pub struct Main {}
+// spawns the level and the drones of the match, lasts a frame
+pub struct Loading {}
+pub struct Title {}
+pub struct Credits {}
+// match configuration before the game starts
+pub struct Lobby {}
+// the finished offline match
+pub struct Results {}
+// playing on a server, the world mirrors its snapshots
+pub struct Online {}
+
+// Services
+pub struct ToExile {
+    pub entity_list: Vec<Entity>,
+}
+
+impl Default for ToExile {
+    fn default() -> Self {
+        Self {
+            entity_list: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+// All bindable actions
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Accelerate,
+    Strike,
+    LockOn,
+    CameraMode,
+    NextTarget,
+    Shield,
+    Dash,
+    Emp,
+    Tractor,
+    Menu,
+    // controls of the other local players by their slot
+    Local(u8, local::Control),
+}
+
+// Bind Inputs and Actions
+impl ActionMapper<Action> for Input {
+    fn action_mapped(&self, action: Action) -> Option<&Button> {
+        let mapper = self.mapper::<Mapper<Action>>();
+        mapper.get_button(action)
+    }
+}
+
+// Keys of the first player followed by the ones of the players sharing the
+// keyboard with it
+pub fn bindings() -> Vec<(Action, Button)> {
+    let mut list = vec![
+        (Action::MoveForward, Button::Key(KeyCode::W)),
+        (Action::MoveBackward, Button::Key(KeyCode::S)),
+        (Action::MoveLeft, Button::Key(KeyCode::A)),
+        (Action::MoveRight, Button::Key(KeyCode::D)),
+        (Action::MoveUp, Button::Key(KeyCode::Space)),
+        (Action::MoveDown, Button::Key(KeyCode::LControl)),
+        (Action::Accelerate, Button::Key(KeyCode::LShift)),
+        (Action::Strike, Button::MouseLeft),
+        (Action::LockOn, Button::MouseRight),
+        (Action::CameraMode, Button::Key(KeyCode::C)),
+        (Action::NextTarget, Button::Key(KeyCode::Tab)),
+        (Action::Shield, Button::Key(KeyCode::Q)),
+        (Action::Dash, Button::Key(KeyCode::E)),
+        (Action::Emp, Button::Key(KeyCode::F)),
+        (Action::Tractor, Button::Key(KeyCode::R)),
+        (Action::Menu, Button::Key(KeyCode::Escape)),
+    ];
+
+    list.extend(local::bindings());
+    list
+}
@@ -0,0 +1,341 @@
+use super::{ arena, drone, flow, hull, net, server, settings::GameMode, };
+
+use dotrix::ecs::{ Mut, Const };
+use dotrix::{ Window, };
+use dotrix::overlay::Overlay;
+
+use dotrix::egui::{
+    self,
+    Egui,
+};
+
+// bots spawned in the classic and king of the beam modes at most
+pub const MAX_BOTS: usize = 20;
+// hosted servers give their bots this much charge, like the dedicated one
+const HOSTED_BOT_CHARGE: f32 = 50.0;
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn next(self) -> Self {
+        match self {
+            Difficulty::Easy   => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard   => Difficulty::Easy,
+        }
+    }
+
+    // aggression of the bots outside of the survival waves, easy bots are
+    // idle targets
+    pub fn aggression(self) -> f32 {
+        match self {
+            Difficulty::Easy   => 0.0,
+            Difficulty::Normal => 0.15,
+            Difficulty::Hard   => 0.3,
+        }
+    }
+
+    // multiplier of the survival wave aggression
+    pub fn wave_factor(self) -> f32 {
+        match self {
+            Difficulty::Easy   => 1.0,
+            Difficulty::Normal => 1.5,
+            Difficulty::Hard   => 2.0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Network {
+    Offline,
+    // run a server in the game and play on it
+    Host,
+    Join,
+}
+
+impl Network {
+    pub fn next(self) -> Self {
+        match self {
+            Network::Offline => Network::Host,
+            Network::Host    => Network::Join,
+            Network::Join    => Network::Offline,
+        }
+    }
+}
+
+// Match being played, the lobby choice when it was loaded. The lobby may
+// change before the next match starts.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Active {
+    pub mode: GameMode,
+    pub bots: usize,
+}
+
+impl Default for Active {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::Classic,
+            bots: 0,
+        }
+    }
+}
+
+// Match chosen in the lobby, used by the next game reset
+pub struct Match {
+    pub mode: GameMode,
+    // path of the level file
+    pub level: String,
+    pub bots: usize,
+    pub difficulty: Difficulty,
+    // local players, one until each gets its own viewport
+    pub players: usize,
+    pub network: Network,
+    // server to join
+    pub address: String,
+    // network simulated for the online games
+    pub conditions: net::Conditions,
+    // levels found in the levels directory, path and name
+    levels: Vec<(String, String)>,
+    host: Option<server::Host>,
+    // why the last start failed
+    status: Option<String>,
+}
+
+impl Default for Match {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::Classic,
+            level: String::from(arena::DEFAULT_LEVEL),
+            bots: MAX_BOTS,
+            difficulty: Difficulty::Easy,
+            players: 1,
+            network: Network::Offline,
+            address: format!("127.0.0.1:{}", net::DEFAULT_PORT),
+            conditions: net::Conditions::default(),
+            levels: arena::list(arena::LEVELS_DIR),
+            host: None,
+            status: None,
+        }
+    }
+}
+
+impl Match {
+    // Level of the match, the default one if it can't be loaded
+    pub fn load_level(&self) -> arena::Level {
+        arena::Level::load(&self.level).unwrap_or_else(|e| {
+            println!("{}, using an empty level", e);
+            arena::Level::default()
+        })
+    }
+
+    fn level_name(&self) -> &str {
+        self.levels.iter()
+            .find(|(path, _)| *path == self.level)
+            .map_or(self.level.as_str(), |(_, name)| name.as_str())
+    }
+
+    fn next_level(&mut self) {
+        if self.levels.is_empty() {
+            return;
+        }
+
+        let next = self.levels.iter()
+            .position(|(path, _)| *path == self.level)
+            .map_or(0, |i| (i + 1) % self.levels.len());
+
+        self.level = self.levels[next].0.clone();
+    }
+
+    // Stops the server hosted for the last match
+    pub fn stop_hosting(&mut self) {
+        self.host = None;
+    }
+
+    // Client of the chosen network, hosting the server first if asked to
+    fn connect(&mut self, hulls: &hull::Hulls) -> Result<net::Client, String> {
+        // a server hosted for an earlier match is stopped
+        self.host = None;
+
+        let mut client = match self.network {
+            Network::Offline => return Ok(net::Client::default()),
+            Network::Host    => {
+                let level = self.load_level();
+                let hulls = hulls.clone();
+                let bots = self.bots;
+                let aggression = self.difficulty.aggression();
+
+                let host = server::Host::start(net::DEFAULT_PORT, move || {
+                    let mut simulation = server::Simulation::new(&level, hulls);
+
+                    for _ in 0..bots {
+                        simulation.spawn_drone(drone::Stats {
+                            aggression,
+                            charge: HOSTED_BOT_CHARGE,
+                            ..Default::default()
+                        });
+                    }
+
+                    simulation
+                }).map_err(|e| format!("Could not host on port {}: {}", net::DEFAULT_PORT, e))?;
+
+                let port = host.local_addr().port();
+                self.host = Some(host);
+
+                net::Client::connect(("127.0.0.1", port))
+                    .map_err(|e| format!("Could not join the hosted server: {}", e))?
+            },
+            Network::Join    => net::Client::connect(self.address.as_str())
+                .map_err(|e| format!("Could not connect to {}: {}", self.address, e))?,
+        };
+
+        client.simulate(self.conditions);
+
+        Ok(client)
+    }
+}
+
+pub fn menu(
+    overlay: Const<Overlay>,
+    hulls: Const<hull::Hulls>,
+    mut game: Mut<Match>,
+    mut client: Mut<net::Client>,
+    mut window: Mut<Window>,
+    mut flow: Mut<flow::Flow>,
+) {
+    window.set_cursor_grab(false);
+    window.set_cursor_visible(true);
+
+    let egui = overlay.get::<Egui>()
+        .expect("Renderer does not contain an Overlay instance");
+
+    let mut start = false;
+
+    egui::containers::Window::new("Drone target")
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::new(0.0, 0.0))
+        .collapsible(false)
+        .resizable(false)
+        .default_width(200.0)
+        .show(&egui.ctx, |ui| {
+            ui.vertical_centered_justified(|ui| {
+                // hosted matches are classic
+                if game.network == Network::Offline {
+                    let mode_label = match game.mode {
+                        GameMode::Classic  => "Mode: classic",
+                        GameMode::Survival => "Mode: survival",
+                        GameMode::KingOfTheBeam => "Mode: king of the beam",
+                    };
+
+                    if ui.button(mode_label).clicked() {
+                        game.mode = game.mode.next();
+                    }
+                }
+
+                // joined matches run the level and the bots of the server
+                if game.network != Network::Join {
+                    if ui.button(format!("Level: {}", game.level_name())).clicked() {
+                        game.next_level();
+                    }
+
+                    if game.mode == GameMode::Survival && game.network == Network::Offline {
+                        ui.label("Bots arrive in waves");
+                    } else {
+                        ui.add(egui::Slider::new(&mut game.bots, 0..=MAX_BOTS).text("Bots"));
+                    }
+
+                    let difficulty_label = match game.difficulty {
+                        Difficulty::Easy   => "Difficulty: easy",
+                        Difficulty::Normal => "Difficulty: normal",
+                        Difficulty::Hard   => "Difficulty: hard",
+                    };
+
+                    if ui.button(difficulty_label).clicked() {
+                        game.difficulty = game.difficulty.next();
+                    }
+                }
+
+                let network_label = match game.network {
+                    Network::Offline => "Offline",
+                    Network::Host    => "Host a server",
+                    Network::Join    => "Join a server",
+                };
+
+                if ui.button(network_label).clicked() {
+                    game.network = game.network.next();
+                }
+
+                // local players wait for a viewport and camera per player
+                match game.network {
+                    Network::Offline => {},
+                    Network::Host    => {
+                        ui.label(format!("Others join on port {}", net::DEFAULT_PORT));
+                    },
+                    Network::Join    => {
+                        ui.text_edit_singleline(&mut game.address);
+                    },
+                }
+
+                if let Some(status) = &game.status {
+                    ui.add(egui::Label::new(status).text_color(egui::Color32::RED));
+                }
+
+                if ui.button("Start").clicked() {
+                    start = true;
+                }
+
+                if ui.button("Back").clicked() {
+                    flow.send(flow::Event::Back);
+                }
+            });
+        });
+
+    if !start {
+        return;
+    }
+
+    // leave the server of the last match before its host stops
+    *client = net::Client::default();
+
+    match game.connect(&hulls) {
+        Ok(connected) => {
+            *client = connected;
+            game.status = None;
+            flow.send(flow::Event::Start);
+        },
+        Err(e) => {
+            println!("{}", e);
+            game.status = Some(e);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_cycle_through_the_list() {
+        let mut game = Match {
+            levels: vec![
+                (String::from("a.ron"), String::from("A")),
+                (String::from("b.ron"), String::from("B")),
+            ],
+            level: String::from("missing.ron"),
+            ..Default::default()
+        };
+
+        // an unknown level shows its path and starts the cycle over
+        assert_eq!(game.level_name(), "missing.ron");
+
+        game.next_level();
+        assert_eq!(game.level_name(), "A");
+
+        game.next_level();
+        game.next_level();
+        assert_eq!(game.level, "a.ron");
+    }
+}
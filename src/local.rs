@@ -0,0 +1,250 @@
+use super::{ Action, drone, };
+
+use dotrix::{
+    Frame,
+    services::{ Input, },
+    input::{ Button, KeyCode, },
+    math::{ Point3, },
+    ecs::{ Mut, Const, },
+    egui,
+};
+
+pub const MAX_PLAYERS: usize = 4;
+
+// heading change of the keyboard players, radians per second
+const TURN_SPEED: f32 = 1.5;
+const MAX_PITCH:  f32 = 1.5;
+
+// Controls of the local players sharing the keyboard, the first player uses
+// the regular actions
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Control {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    TurnLeft,
+    TurnRight,
+    TurnUp,
+    TurnDown,
+    Accelerate,
+    Strike,
+    Shield,
+    Dash,
+    Emp,
+    Tractor,
+    LockOn,
+}
+
+// Players sharing this machine and its screen. The engine renders through a
+// single camera, there is no split-screen: the camera frames all local
+// players and each of them has a status bar in a corner of the screen.
+pub struct Local {
+    pub players: usize,
+    // heading of every player, y and xz camera angles
+    headings: [(f32, f32); MAX_PLAYERS],
+}
+
+impl Default for Local {
+    fn default() -> Self {
+        Self {
+            players: 1,
+            headings: [(0.0, 0.0); MAX_PLAYERS],
+        }
+    }
+}
+
+impl Local {
+    pub fn reset(&mut self, players: usize) {
+        self.players = players.max(1).min(MAX_PLAYERS);
+        self.headings = [(0.0, 0.0); MAX_PLAYERS];
+    }
+
+    // Turns the keyboard players' headings
+    pub fn turn(&mut self, input: &Input, dt: f32) {
+        for slot in 1..self.players {
+            let held = |control| input.is_action_hold(Action::Local(slot as u8, control));
+            let (y_angle, xz_angle) = &mut self.headings[slot];
+
+            if held(Control::TurnLeft) {
+                *y_angle = *y_angle - TURN_SPEED * dt;
+            }
+            if held(Control::TurnRight) {
+                *y_angle = *y_angle + TURN_SPEED * dt;
+            }
+            if held(Control::TurnUp) {
+                *xz_angle = (*xz_angle + TURN_SPEED * dt).min(MAX_PITCH);
+            }
+            if held(Control::TurnDown) {
+                *xz_angle = (*xz_angle - TURN_SPEED * dt).max(-MAX_PITCH);
+            }
+        }
+    }
+
+    // Flight controls of a keyboard player
+    pub fn intent(&self, input: &Input, slot: usize) -> drone::Intent {
+        let action = |control| Action::Local(slot as u8, control);
+        let (y_angle, xz_angle) = self.headings[slot];
+
+        drone::Intent {
+            forward:    input.is_action_hold(action(Control::Forward)),
+            backward:   input.is_action_hold(action(Control::Backward)),
+            left:       input.is_action_hold(action(Control::Left)),
+            right:      input.is_action_hold(action(Control::Right)),
+            accelerate: input.is_action_hold(action(Control::Accelerate)),
+            strike:     input.is_action_hold(action(Control::Strike)),
+            release:    input.is_action_deactivated(action(Control::Strike)),
+            y_angle,
+            xz_angle,
+        }
+    }
+}
+
+// Action of the player in the slot, the first player has the regular ones
+pub fn action(slot: usize, primary: Action, control: Control) -> Action {
+    match slot {
+        0 => primary,
+        _ => Action::Local(slot as u8, control),
+    }
+}
+
+pub fn update(
+    input: Const<Input>,
+    frame: Const<Frame>,
+    mut local: Mut<Local>,
+) {
+    let dt = 1.0 / frame.fps();
+
+    local.turn(&input, dt);
+}
+
+// Keys of the players sharing the keyboard with the first one
+pub fn bindings() -> Vec<(Action, Button)> {
+    let layouts: [(u8, [KeyCode; 15]); 3] = [
+        (1, [
+            KeyCode::Up, KeyCode::Down, KeyCode::Left, KeyCode::Right,
+            KeyCode::Numpad4, KeyCode::Numpad6, KeyCode::Numpad8, KeyCode::Numpad5,
+            KeyCode::RShift, KeyCode::RControl,
+            KeyCode::Numpad7, KeyCode::Numpad9, KeyCode::Numpad1, KeyCode::Numpad3,
+            KeyCode::Numpad0,
+        ]),
+        (2, [
+            KeyCode::I, KeyCode::K, KeyCode::J, KeyCode::L,
+            KeyCode::U, KeyCode::O, KeyCode::Y, KeyCode::H,
+            KeyCode::N, KeyCode::M,
+            KeyCode::Key7, KeyCode::Key8, KeyCode::Key9, KeyCode::Key0,
+            KeyCode::Comma,
+        ]),
+        (3, [
+            KeyCode::Home, KeyCode::End, KeyCode::Delete, KeyCode::PageDown,
+            KeyCode::Insert, KeyCode::PageUp, KeyCode::LBracket, KeyCode::RBracket,
+            KeyCode::Backslash, KeyCode::Return,
+            KeyCode::Minus, KeyCode::Equals, KeyCode::Semicolon, KeyCode::Apostrophe,
+            KeyCode::Slash,
+        ]),
+    ];
+
+    let controls = [
+        Control::Forward, Control::Backward, Control::Left, Control::Right,
+        Control::TurnLeft, Control::TurnRight, Control::TurnUp, Control::TurnDown,
+        Control::Accelerate, Control::Strike,
+        Control::Shield, Control::Dash, Control::Emp, Control::Tractor,
+        Control::LockOn,
+    ];
+
+    let mut list = Vec::new();
+
+    for (slot, keys) in layouts.iter() {
+        for (control, key) in controls.iter().zip(keys.iter()) {
+            list.push((Action::Local(*slot, *control), Button::Key(*key)));
+        }
+    }
+
+    list
+}
+
+// spawn position of the player in the slot
+pub fn spawn_position(slot: usize) -> Point3 {
+    let offsets: [[f32; 3]; MAX_PLAYERS] = [
+        [ 10.0, 0.0,   0.0],
+        [-10.0, 0.0,   0.0],
+        [  0.0, 0.0,  10.0],
+        [  0.0, 0.0, -10.0],
+    ];
+
+    let offset = offsets[slot % MAX_PLAYERS];
+
+    Point3::new(offset[0], offset[1], offset[2])
+}
+
+// Screen areas the status bars of the players are placed in the corners of:
+// the whole screen, its halves or its quarters
+pub fn hud_areas(players: usize, screen: egui::Rect) -> Vec<egui::Rect> {
+    let center = screen.center();
+
+    match players {
+        0 | 1 => vec![screen],
+        2     => vec![
+            egui::Rect::from_min_max(screen.min, egui::Pos2::new(center.x, screen.max.y)),
+            egui::Rect::from_min_max(egui::Pos2::new(center.x, screen.min.y), screen.max),
+        ],
+        _     => {
+            let quarters = vec![
+                egui::Rect::from_min_max(screen.min, center),
+                egui::Rect::from_min_max(
+                    egui::Pos2::new(center.x, screen.min.y),
+                    egui::Pos2::new(screen.max.x, center.y),
+                ),
+                egui::Rect::from_min_max(
+                    egui::Pos2::new(screen.min.x, center.y),
+                    egui::Pos2::new(center.x, screen.max.y),
+                ),
+                egui::Rect::from_min_max(center, screen.max),
+            ];
+
+            quarters.into_iter().take(players.min(MAX_PLAYERS)).collect()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hud_areas_cover_the_screen() {
+        let screen = egui::Rect::from_min_max(
+            egui::Pos2::new(0.0, 0.0), egui::Pos2::new(1280.0, 720.0)
+        );
+
+        for players in 1..=MAX_PLAYERS {
+            let areas = hud_areas(players, screen);
+            assert_eq!(areas.len(), players);
+
+            for area in areas.iter() {
+                assert!(screen.contains(area.min) && screen.contains(area.max));
+            }
+        }
+
+        assert_eq!(hud_areas(2, screen)[1].width(), 640.0);
+        assert_eq!(hud_areas(4, screen)[3].min, screen.center());
+    }
+
+    #[test]
+    fn the_first_player_has_the_regular_actions() {
+        assert_eq!(action(0, Action::LockOn, Control::LockOn), Action::LockOn);
+        assert_eq!(action(2, Action::LockOn, Control::LockOn), Action::Local(2, Control::LockOn));
+    }
+
+    #[test]
+    fn bindings_are_unique() {
+        // the other players' keys against each other and the first player's
+        let list = crate::bindings();
+
+        assert!(bindings().iter().all(|binding| list.contains(binding)));
+
+        for (i, (_, button)) in list.iter().enumerate() {
+            assert!(!list[i + 1..].iter().any(|(_, other)| other == button));
+        }
+    }
+}
@@ -1,8 +1,8 @@
-mod physics;
-mod drone;
-mod beam;
-mod settings;
-mod info_panel;
+use drone_target::{
+    physics, drone, beam, settings, info_panel, radar, view, targeting, survival,
+    capture, pickup, ability, arena, hull, debug, local, net, lobby, flow,
+    Action, ToExile, Pause, Main, Loading, Online, Lobby, Title, Credits, Results,
+};
 
 use rapier3d;
 
@@ -22,51 +22,100 @@ use dotrix::{
 
     sky::{ skybox, SkyBox, },
     pbr::{ self, Light, },
-    input::{ ActionMapper, Button, KeyCode, Mapper, },
+    input::{ Mapper, },
     camera,
     math::{ Point3, Vec3 },
     ecs::{ Entity, },
 };
 
-// States
-struct Pause {
-    handled: bool,
-}
-
-struct Main {}
-struct Initialization {}
+use std::time::Duration;
 
-// Services
-pub struct ToExile {
-    pub entity_list: Vec<Entity>,
-}
-
-impl Default for ToExile {
-    fn default() -> Self {
-        Self {
-            entity_list: Vec::new(),
+fn main() {
+    // join a server with `--connect HOST:PORT`, `--latency MS --jitter MS
+    // --loss P` simulate a bad network on localhost
+    let mut game = lobby::Match::default();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let value = args.next();
+
+        match (arg.as_str(), value) {
+            ("--connect", Some(value)) => {
+                game.network = lobby::Network::Join;
+                game.address = value;
+            },
+            ("--latency", Some(value)) => game.conditions.latency =
+                Duration::from_millis(value.parse().expect("Invalid latency")),
+            ("--jitter", Some(value))  => game.conditions.jitter =
+                Duration::from_millis(value.parse().expect("Invalid jitter")),
+            ("--loss", Some(value))    => game.conditions.loss = value.parse().expect("Invalid loss"),
+            _ => println!("Unknown argument {}", arg),
         }
     }
-}
 
-fn main() {
     Dotrix::application("drone-target")
         .with(System::from(startup))
         .with(System::from(settings::startup))
 
-        .with(System::from(settings::init).with(State::on::<Initialization>()))
-        // init_level should be called the last as it pops init state
-        .with(System::from(init_level).with(State::on::<Initialization>()))
+        .with(System::from(flow::title).with(State::on::<Title>()))
+        .with(System::from(flow::credits).with(State::on::<Credits>()))
+        .with(System::from(lobby::menu).with(State::on::<Lobby>()))
+        .with(System::from(settings::init).with(State::on::<Loading>()))
+        .with(System::from(init_level).with(State::on::<Loading>()))
+        .with(System::from(flow::results).with(State::on::<Results>()))
 
-        .with(System::from(settings::ui_update).with(State::off::<Pause>()))
+        .with(System::from(settings::ui_update).with(State::on::<Main>()))
+        .with(System::from(settings::ui_update).with(State::on::<Online>()))
         .with(System::from(settings::pause_menu).with(State::on::<Pause>()))
         .with(System::from(camera::control).with(State::on::<Main>()))
         .with(System::from(physics::step).with(State::on::<Main>()))
+        .with(System::from(targeting::lock).with(State::on::<Main>()))
+        .with(System::from(local::update).with(State::on::<Main>()))
+        .with(System::from(ability::update).with(State::on::<Main>()))
         .with(System::from(drone::control).with(State::on::<Main>()))
+        .with(System::from(drone::collisions).with(State::on::<Main>()))
         .with(System::from(beam::gravity).with(State::on::<Main>()))
-        .with(System::from(drone::exile))
-        .with(System::from(exile))
-        .with(System::from(info_panel::update))
+        .with(System::from(arena::boundary).with(State::on::<Main>()))
+        .with(System::from(view::control).with(State::on::<Main>()))
+        .with(System::from(survival::update).with(State::on::<Main>()))
+        .with(System::from(capture::update).with(State::on::<Main>()))
+        .with(System::from(pickup::spawner).with(State::on::<Main>()))
+        .with(System::from(pickup::collect).with(State::on::<Main>()))
+        .with(System::from(flow::referee).with(State::on::<Main>()))
+        .with(System::from(camera::control).with(State::on::<Online>()))
+        .with(System::from(net::receive).with(State::on::<Online>()))
+        .with(System::from(net::send).with(State::on::<Online>()))
+        // the link stays alive in the pause menu of the online games
+        .with(System::from(net::receive).with(State::on::<Pause>()))
+        .with(System::from(net::send).with(State::on::<Pause>()))
+        .with(System::from(targeting::lock).with(State::on::<Online>()))
+        .with(System::from(view::control).with(State::on::<Online>()))
+
+        // the HUD stays over the paused game
+        .with(System::from(info_panel::update).with(State::on::<Main>()))
+        .with(System::from(info_panel::update).with(State::on::<Online>()))
+        .with(System::from(info_panel::update).with(State::on::<Pause>()))
+        .with(System::from(radar::update).with(State::on::<Main>()))
+        .with(System::from(radar::update).with(State::on::<Online>()))
+        .with(System::from(radar::update).with(State::on::<Pause>()))
+        .with(System::from(targeting::reticle).with(State::on::<Main>()))
+        .with(System::from(targeting::reticle).with(State::on::<Online>()))
+        .with(System::from(targeting::reticle).with(State::on::<Pause>()))
+        .with(System::from(ability::bubbles).with(State::on::<Main>()))
+        .with(System::from(ability::bubbles).with(State::on::<Online>()))
+        .with(System::from(ability::bubbles).with(State::on::<Pause>()))
+        .with(System::from(debug::draw).with(State::on::<Main>()))
+        .with(System::from(debug::draw).with(State::on::<Online>()))
+        .with(System::from(debug::draw).with(State::on::<Pause>()))
+        .with(System::from(capture::ui).with(State::on::<Main>()))
+        .with(System::from(capture::ui).with(State::on::<Pause>()))
+
+        // entities queued in any state, e.g. by the level loading, are removed
+        .with(System::from(physics::exile))
+        .with(System::from(settings::track_window))
+        // screen changes should happen the last, after every system of the
+        // current screen has run
+        .with(System::from(flow::apply))
 
         .with(Service::from(rapier3d::dynamics::RigidBodySet::new()))
         .with(Service::from(rapier3d::geometry::ColliderSet::new()))
@@ -74,8 +123,21 @@ fn main() {
         .with(Service::from(rapier3d::geometry::BroadPhase::new()))
         .with(Service::from(rapier3d::geometry::NarrowPhase::new()))
         .with(Service::from(rapier3d::dynamics::CCDSolver::new()))
+        .with(Service::from(rapier3d::pipeline::QueryPipeline::new()))
         .with(Service::from(settings::Settings::default()))
         .with(Service::from(ToExile::default()))
+        .with(Service::from(targeting::LockOn::default()))
+        .with(Service::from(view::Rig::default()))
+        .with(Service::from(survival::Survival::default()))
+        .with(Service::from(capture::Capture::default()))
+        .with(Service::from(pickup::Spawner::default()))
+        .with(Service::from(arena::Arena::default()))
+        .with(Service::from(hull::Hulls::default()))
+        .with(Service::from(local::Local::default()))
+        .with(Service::from(game))
+        .with(Service::from(lobby::Active::default()))
+        .with(Service::from(net::Client::default()))
+        .with(Service::from(flow::Flow::default()))
 
         .with(skybox::extension)
         .with(pbr::extension)
@@ -89,12 +151,12 @@ fn startup(
     mut state: Mut<State>,
     mut world: Mut<World>,
     mut assets: Mut<Assets>,
-    mut bodies: Mut<rapier3d::dynamics::RigidBodySet>,
-    mut colliders: Mut<rapier3d::geometry::ColliderSet>,
     mut input: Mut<Input>,
+    mut hulls: Mut<hull::Hulls>,
 ) {
     input.set_mapper(Box::new(Mapper::<Action>::new()));
     load_assets(&mut assets);
+    load_hulls(&mut hulls);
     init_controls(&mut input);
 
     // Spawn skybox
@@ -115,27 +177,29 @@ fn startup(
         Pipeline::default()
     )));
 
-    beam::spawn(
-        &mut world,
-        &mut assets,
-        &mut bodies,
-        &mut colliders,
-        Point3::new(0.0, 0.0, 0.0),
-    );
-
     init_light(&mut world);
 
-    state.push(Initialization {});
+    state.push(Title {});
 }
 
 fn init_level(
-    mut state: Mut<State>,
     mut world: Mut<World>,
     mut assets: Mut<Assets>,
     mut camera: Mut<Camera>,
+    mut rig: Mut<view::Rig>,
     mut bodies: Mut<rapier3d::dynamics::RigidBodySet>,
     mut colliders: Mut<rapier3d::geometry::ColliderSet>,
+    hulls: Const<hull::Hulls>,
     mut to_exile: Mut<ToExile>,
+    mut arena: Mut<arena::Arena>,
+    game: Const<lobby::Match>,
+    mut active: Mut<lobby::Active>,
+    mut survival: Mut<survival::Survival>,
+    mut capture: Mut<capture::Capture>,
+    mut spawner: Mut<pickup::Spawner>,
+    mut local: Mut<local::Local>,
+    mut client: Mut<net::Client>,
+    mut flow: Mut<flow::Flow>,
 ) {
     // despawn all drones
     let query = world.query::<(
@@ -146,14 +210,82 @@ fn init_level(
         to_exile.entity_list.push(*entity);
     }
 
+    // and pickups
+    let query = world.query::<(
+        &Entity, &pickup::Pickup
+    )>();
+
+    for (entity, _) in query {
+        to_exile.entity_list.push(*entity);
+    }
+
+    spawner.reset();
+
+    // the level of the new match replaces the last one
+    arena::clear(&world, &mut to_exile.entity_list);
+
     init_camera(&mut camera);
+    rig.reset(camera.distance);
+
+    // the server spawns the drones and sends its level, a restarted match
+    // knows it already
+    if client.is_online() {
+        if let Some(level) = client.level() {
+            arena::spawn(
+                level,
+                &mut arena,
+                &mut world,
+                &mut assets,
+                &mut bodies,
+                &mut colliders,
+                &hulls,
+            );
+        }
 
-    init_drones(&mut world, &mut assets, &mut bodies, &mut colliders);
+        // its matches are classic
+        *active = lobby::Active::default();
+        local.reset(1);
+        client.forget();
+        flow.send(flow::Event::Joined);
+        return;
+    }
 
-    //Clear all states
-    while state.pop_any().is_some() {};
+    let level = game.load_level();
 
-    state.push(Main {});
+    arena::spawn(
+        &level,
+        &mut arena,
+        &mut world,
+        &mut assets,
+        &mut bodies,
+        &mut colliders,
+        &hulls,
+    );
+
+    *active = lobby::Active { mode: game.mode, bots: game.bots };
+
+    local.reset(game.players);
+    let players = local.players;
+    let aggression = game.difficulty.aggression();
+
+    init_players(&mut world, &mut assets, &mut bodies, &mut colliders, &hulls, players);
+
+    match game.mode {
+        settings::GameMode::Classic  => {
+            init_bots(&mut world, &mut assets, &mut bodies, &mut colliders, &hulls, game.bots, aggression);
+        },
+        settings::GameMode::Survival => {
+            // bots arrive in waves
+            survival.reset(game.difficulty.wave_factor());
+        },
+        settings::GameMode::KingOfTheBeam => {
+            // the bots fly in from outside and contest the zone
+            init_capturers(&mut world, &mut assets, &mut bodies, &mut colliders, &hulls, &level, game.bots, aggression);
+            capture.reset();
+        },
+    }
+
+    flow.send(flow::Event::Loaded);
 }
 
 fn init_camera(camera: &mut Camera) {
@@ -177,24 +309,52 @@ fn load_assets(
 
     assets.import("assets/energy_beam/energy_beam.gltf");
     assets.import("assets/drone/drone.gltf");
+    assets.import("assets/asteroid/asteroid.gltf");
+    assets.import("assets/pillar/pillar.gltf");
+}
+
+// Colliders are built from the same models the drones and beams are rendered with
+fn load_hulls(
+    hulls: &mut hull::Hulls,
+) {
+    for (name, path) in hull::MODELS.iter() {
+        if let Err(e) = hulls.load(name, path) {
+            println!("{}, using a ball collider", e);
+        }
+    }
 }
 
-fn init_drones(
+fn init_players(
     world: &mut World,
     assets: &mut Assets,
     bodies: &mut rapier3d::dynamics::RigidBodySet,
     colliders: &mut rapier3d::geometry::ColliderSet,
+    hulls: &hull::Hulls,
+    players: usize,
 ) {
-    drone::spawn(
-        world,
-        assets,
-        bodies,
-        colliders,
-        Point3::new(10.0, 0.0, 0.0),
-        true,
-    );
+    for slot in 0..players {
+        drone::spawn(
+            world,
+            assets,
+            bodies,
+            colliders,
+            hulls,
+            local::spawn_position(slot),
+            drone::Stats { is_player: true, slot, ..Default::default() },
+        );
+    }
+}
 
-    let positions: [[f32; 3]; 20] = [
+fn init_bots(
+    world: &mut World,
+    assets: &mut Assets,
+    bodies: &mut rapier3d::dynamics::RigidBodySet,
+    colliders: &mut rapier3d::geometry::ColliderSet,
+    hulls: &hull::Hulls,
+    count: usize,
+    aggression: f32,
+) {
+    let positions: [[f32; 3]; lobby::MAX_BOTS] = [
         [ 80.0,  10.0, -90.0],
         [-50.0,  20.0,  30.0],
         [100.0, -50.0, -40.0],
@@ -217,15 +377,45 @@ fn init_drones(
         [-90.0,  85.0,  10.0],
     ];
 
-    for i in 0..positions.len() {
-        println!("{}", i);
+    for position in positions.iter().take(count) {
         drone::spawn(
             world,
             assets,
             bodies,
             colliders,
-            Point3::new(positions[i][0], positions[i][1], positions[i][2]),
-            false,
+            hulls,
+            Point3::new(position[0], position[1], position[2]),
+            drone::Stats { aggression, ..Default::default() },
+        );
+    }
+}
+
+fn init_capturers(
+    world: &mut World,
+    assets: &mut Assets,
+    bodies: &mut rapier3d::dynamics::RigidBodySet,
+    colliders: &mut rapier3d::geometry::ColliderSet,
+    hulls: &hull::Hulls,
+    level: &arena::Level,
+    count: usize,
+    aggression: f32,
+) {
+    let radius_far = beam::Stats::default().radius_far;
+
+    for position in capture::spawn_points(&level.beams, radius_far, count) {
+        drone::spawn(
+            world,
+            assets,
+            bodies,
+            colliders,
+            hulls,
+            position,
+            drone::Stats {
+                aggression: capture::CONTEST_AGGRESSION + aggression,
+                charge: capture::BOT_CHARGE,
+                capturer: true,
+                ..Default::default()
+            },
         );
     }
 }
@@ -259,45 +449,7 @@ fn init_light(world: &mut World) {
     )));
 }
 
-fn exile(mut world: Mut<World>, mut to_exile: Mut<ToExile>) {
-
-    for i in 0..to_exile.entity_list.len() {
-        world.exile(to_exile.entity_list[i]);
-    }
-
-    to_exile.entity_list = Vec::new();
-}
-
 fn init_controls(input: &mut Input) {
-    // Map W key to Run Action
     input.mapper_mut::<Mapper<Action>>()
-        .set(vec![
-            (Action::MoveForward, Button::Key(KeyCode::W)),
-            (Action::MoveBackward, Button::Key(KeyCode::S)),
-            (Action::MoveLeft, Button::Key(KeyCode::A)),
-            (Action::MoveRight, Button::Key(KeyCode::D)),
-            (Action::Accelerate, Button::Key(KeyCode::LShift)),
-            (Action::Strike, Button::MouseLeft),
-            (Action::Menu, Button::Key(KeyCode::Escape)),
-        ]);
-}
-
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
-// All bindable actions
-pub enum Action {
-    MoveForward,
-    MoveBackward,
-    MoveLeft,
-    MoveRight,
-    Accelerate,
-    Strike,
-    Menu,
-}
-
-// Bind Inputs and Actions
-impl ActionMapper<Action> for Input {
-    fn action_mapped(&self, action: Action) -> Option<&Button> {
-        let mapper = self.mapper::<Mapper<Action>>();
-        mapper.get_button(action)
-    }
+        .set(drone_target::bindings());
 }
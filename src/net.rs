@@ -0,0 +1,847 @@
+use super::{ arena, beam, drone, hull, prediction::Prediction, wire, Pause, ToExile };
+
+use serde::{ Serialize, Deserialize, };
+
+use bincode::Options;
+
+use rapier3d::{
+    dynamics::{ RigidBodySet, RigidBodyHandle, },
+    geometry::{ ColliderSet, },
+    na::{ Isometry3, Quaternion, Translation3, UnitQuaternion, Vector3, },
+};
+
+use rand::Rng;
+
+use dotrix::{
+    Transform,
+    services::{ Assets, Camera, Input, World, },
+    math::{ Point3, },
+    ecs::{ Mut, Const, Entity, },
+    Frame,
+    State,
+};
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::{ SocketAddr, ToSocketAddrs, UdpSocket, };
+use std::time::{ Duration, Instant, };
+
+pub const DEFAULT_PORT: u16 = 7777;
+// server simulation ticks per second, the flight model is tuned per frame
+// so it matches the client frame rate
+pub const TICK_RATE: u32 = 60;
+// ticks between snapshots sent to the clients
+pub const SNAPSHOT_INTERVAL: u32 = 3;
+// peers silent for this long are dropped
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+// clients render the remote state this far in the past, seconds
+const INTERPOLATION_DELAY: f32 = 0.1;
+// snapshots kept for the interpolation
+const TIMELINE_LENGTH: usize = 32;
+// interval of the join requests until the server answers
+const JOIN_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_PACKET: usize = 65507;
+// ticks a client catches up at most after a slow frame
+const MAX_CATCH_UP: u32 = 5;
+
+// Network id of a drone shared by the server and the clients
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct NetId(pub u32);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Message {
+    // client to server, `baseline` is the tick of the newest snapshot received
+    Join,
+    Input { tick: u32, baseline: u32, intent: drone::Intent },
+    Leave,
+    // server to client, `id` is the drone of the client and `level` the one
+    // the server runs
+    Welcome { id: u32, level: arena::Level },
+    // snapshot in the wire format
+    Snapshot(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DroneState {
+    pub id: u32,
+    pub is_player: bool,
+    pub position: [f32; 3],
+    // quaternion i, j, k, w
+    pub rotation: [f32; 4],
+    pub velocity: [f32; 3],
+    // angular velocity
+    pub spin: [f32; 3],
+    pub health: f32,
+    pub charge: f32,
+    pub strike_charge: f32,
+    pub shield: f32,
+    pub boost: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeamState {
+    pub id: u32,
+    pub position: [f32; 3],
+    pub stats: beam::Stats,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub tick: u32,
+    // client tick of the last input applied to the receiver's drone
+    pub ack: u32,
+    pub drones: Vec<DroneState>,
+    pub beams: Vec<BeamState>,
+}
+
+// varint integers keep the messages short
+pub fn encode(message: &Message) -> Vec<u8> {
+    bincode::DefaultOptions::new()
+        .serialize(message)
+        .expect("Messages are always serializable")
+}
+
+// None for malformed packets
+pub fn decode(data: &[u8]) -> Option<Message> {
+    bincode::DefaultOptions::new()
+        .with_limit(MAX_PACKET as u64)
+        .deserialize(data)
+        .ok()
+}
+
+// Network conditions simulated by a link, to test on localhost. The delay
+// applies to each direction of the link.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Conditions {
+    pub latency: Duration,
+    // the delay varies randomly by up to this much
+    pub jitter: Duration,
+    // portion of the packets lost (0-1)
+    pub loss: f32,
+}
+
+impl Default for Conditions {
+    fn default() -> Self {
+        Self {
+            latency: Duration::from_millis(0),
+            jitter: Duration::from_millis(0),
+            loss: 0.0,
+        }
+    }
+}
+
+impl Conditions {
+    fn is_perfect(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // None if the packet is lost
+    fn delay(&self) -> Option<Duration> {
+        let mut rng = rand::thread_rng();
+
+        if self.loss > 0.0 && rng.gen::<f32>() < self.loss {
+            return None;
+        }
+
+        let jitter = self.jitter.as_secs_f32() * rng.gen_range(-1.0..=1.0);
+        let delay = (self.latency.as_secs_f32() + jitter).max(0.0);
+
+        Some(Duration::from_secs_f32(delay))
+    }
+}
+
+// Non-blocking UDP endpoint
+pub struct Link {
+    socket: UdpSocket,
+    conditions: Conditions,
+    // packets held back by the simulated conditions and their due time
+    outgoing: Vec<(Instant, SocketAddr, Vec<u8>)>,
+    incoming: Vec<(Instant, SocketAddr, Message)>,
+}
+
+impl Link {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            conditions: Conditions::default(),
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+        })
+    }
+
+    pub fn simulate(&mut self, conditions: Conditions) {
+        self.conditions = conditions;
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    // Sends the message, UDP doesn't guarantee it arrives anyway so errors
+    // are only logged
+    pub fn send(&mut self, message: &Message, to: SocketAddr) {
+        if self.conditions.is_perfect() {
+            self.send_now(&encode(message), to);
+            return;
+        }
+
+        if let Some(delay) = self.conditions.delay() {
+            self.outgoing.push((Instant::now() + delay, to, encode(message)));
+        }
+
+        self.flush();
+    }
+
+    // Sends the delayed packets that are due
+    pub fn flush(&mut self) {
+        let now = Instant::now();
+        let (due, held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.outgoing)
+            .into_iter()
+            .partition(|(time, _, _)| *time <= now);
+
+        self.outgoing = held;
+
+        for (_, to, data) in due {
+            self.send_now(&data, to);
+        }
+    }
+
+    fn send_now(&self, data: &[u8], to: SocketAddr) {
+        if let Err(e) = self.socket.send_to(data, to) {
+            println!("Could not send to {}: {}", to, e);
+        }
+    }
+
+    // Messages received since the last call
+    pub fn receive(&mut self) -> Vec<(SocketAddr, Message)> {
+        self.flush();
+
+        let mut buffer = [0u8; MAX_PACKET];
+        let mut list = Vec::new();
+
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((size, from)) => {
+                    if let Some(message) = decode(&buffer[..size]) {
+                        list.push((from, message));
+                    }
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                // e.g. the peer port is closed, the next frame tries again
+                Err(e) => {
+                    println!("Could not receive: {}", e);
+                    break;
+                },
+            }
+        }
+
+        if self.conditions.is_perfect() && self.incoming.is_empty() {
+            return list;
+        }
+
+        let now = Instant::now();
+
+        for (from, message) in list {
+            if let Some(delay) = self.conditions.delay() {
+                self.incoming.push((now + delay, from, message));
+            }
+        }
+
+        let (due, held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.incoming)
+            .into_iter()
+            .partition(|(time, _, _)| *time <= now);
+
+        self.incoming = held;
+
+        due.into_iter().map(|(_, from, message)| (from, message)).collect()
+    }
+}
+
+// Snapshots received by a client, sampled in the past to hide the jitter
+pub struct Timeline {
+    snapshots: VecDeque<Snapshot>,
+    // tick of the newest snapshot and the client time it arrived at
+    latest: Option<(u32, f32)>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            latest: None,
+        }
+    }
+}
+
+impl Timeline {
+    // Adds the snapshot received at `now`, late and duplicated snapshots
+    // are dropped and false is returned
+    pub fn push(&mut self, snapshot: Snapshot, now: f32) -> bool {
+        if let Some((tick, _)) = self.latest {
+            if snapshot.tick <= tick {
+                return false;
+            }
+        }
+
+        self.latest = Some((snapshot.tick, now));
+        self.snapshots.push_back(snapshot);
+
+        while self.snapshots.len() > TIMELINE_LENGTH {
+            self.snapshots.pop_front();
+        }
+
+        true
+    }
+
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.snapshots.back()
+    }
+
+    // Snapshot of the tick, the baseline of the deltas
+    pub fn get(&self, tick: u32) -> Option<&Snapshot> {
+        self.snapshots.iter().find(|snapshot| snapshot.tick == tick)
+    }
+
+    // Drones interpolated at the server time estimated for `now`
+    pub fn sample(&self, now: f32) -> Vec<DroneState> {
+        let (tick, arrival) = match self.latest {
+            Some(latest) => latest,
+            None         => return Vec::new(),
+        };
+
+        let time = tick_time(tick) + (now - arrival) - INTERPOLATION_DELAY;
+
+        let first = self.snapshots.front().unwrap();
+        let last = self.snapshots.back().unwrap();
+
+        if time <= tick_time(first.tick) {
+            return first.drones.clone();
+        }
+
+        if time >= tick_time(last.tick) {
+            return last.drones.clone();
+        }
+
+        for (a, b) in self.snapshots.iter().zip(self.snapshots.iter().skip(1)) {
+            let (start, end) = (tick_time(a.tick), tick_time(b.tick));
+
+            if time < start || time > end {
+                continue;
+            }
+
+            let t = (time - start) / (end - start);
+
+            // drones missing in the older snapshot just appear
+            return b.drones.iter()
+                .map(|state| match a.drones.iter().find(|old| old.id == state.id) {
+                    Some(old) => interpolate(old, state, t),
+                    None      => state.clone(),
+                })
+                .collect();
+        }
+
+        last.drones.clone()
+    }
+}
+
+fn tick_time(tick: u32) -> f32 {
+    tick as f32 / TICK_RATE as f32
+}
+
+// State between `a` and `b`, the stats are taken from the newer state
+pub fn interpolate(a: &DroneState, b: &DroneState, t: f32) -> DroneState {
+    let lerp = |a: [f32; 3], b: [f32; 3]| [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ];
+
+    let from = to_quaternion(a.rotation);
+    let to = to_quaternion(b.rotation);
+    let rotation = from.try_slerp(&to, t, 1.0e-6).unwrap_or(to);
+
+    DroneState {
+        position: lerp(a.position, b.position),
+        velocity: lerp(a.velocity, b.velocity),
+        spin: lerp(a.spin, b.spin),
+        rotation: from_quaternion(&rotation),
+        ..b.clone()
+    }
+}
+
+pub fn isometry(state: &DroneState) -> Isometry3<f32> {
+    Isometry3::from_parts(
+        Translation3::new(state.position[0], state.position[1], state.position[2]),
+        to_quaternion(state.rotation),
+    )
+}
+
+pub fn vector(v: [f32; 3]) -> Vector3<f32> {
+    Vector3::new(v[0], v[1], v[2])
+}
+
+pub fn to_quaternion(q: [f32; 4]) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_quaternion(Quaternion::new(q[3], q[0], q[1], q[2]))
+}
+
+pub fn from_quaternion(q: &UnitQuaternion<f32>) -> [f32; 4] {
+    [q.i, q.j, q.k, q.w]
+}
+
+// Connection of the game to a server, offline by default
+pub struct Client {
+    link: Option<Link>,
+    server: Option<SocketAddr>,
+    // drone of this client
+    pub id: Option<u32>,
+    tick: u32,
+    // time not simulated yet, the inputs are sent at the tick rate
+    accumulator: f32,
+    // a strike released between two ticks
+    release: bool,
+    timeline: Timeline,
+    clock: Instant,
+    joined_at: Option<Instant>,
+    // last word of the server, the client joins again when it goes silent
+    heard_at: Option<Instant>,
+    // bodies of the mirrored drones by their id
+    bodies: Vec<(u32, RigidBodyHandle)>,
+    // level the server runs, needed to predict the own drone
+    level: Option<(arena::Level, hull::Hulls)>,
+    // level of the welcome, not spawned yet
+    arrived: Option<arena::Level>,
+    prediction: Option<Prediction>,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self {
+            link: None,
+            server: None,
+            id: None,
+            tick: 0,
+            accumulator: 0.0,
+            release: false,
+            timeline: Timeline::default(),
+            clock: Instant::now(),
+            joined_at: None,
+            heard_at: None,
+            bodies: Vec::new(),
+            level: None,
+            arrived: None,
+            prediction: None,
+        }
+    }
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(server: A) -> io::Result<Self> {
+        let server = server.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No server address"))?;
+
+        let bind = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+
+        Ok(Self {
+            link: Some(Link::bind(bind)?),
+            server: Some(server),
+            ..Default::default()
+        })
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.link.is_some()
+    }
+
+    // Delays and drops the packets of the connection
+    pub fn simulate(&mut self, conditions: Conditions) {
+        if let Some(link) = self.link.as_mut() {
+            link.simulate(conditions);
+        }
+    }
+
+    // The own drone is predicted only when the level is known, without it
+    // the drone is interpolated like the others
+    pub fn prepare(&mut self, level: arena::Level, hulls: hull::Hulls) {
+        self.level = Some((level, hulls));
+    }
+
+    // Level of the server, None until the client is welcomed
+    pub fn level(&self) -> Option<&arena::Level> {
+        self.level.as_ref().map(|(level, _)| level)
+    }
+
+    // Mirrored drones are spawned again after the local world reset
+    pub fn forget(&mut self) {
+        self.bodies.clear();
+    }
+
+    fn send(&mut self, message: &Message) {
+        if let (Some(link), Some(server)) = (self.link.as_mut(), self.server) {
+            link.send(message, server);
+        }
+    }
+
+    // Handles received messages, asking to join until the server answers
+    fn poll(&mut self) {
+        let now = self.clock.elapsed().as_secs_f32();
+
+        let messages = match self.link.as_mut() {
+            Some(link) => link.receive(),
+            None       => return,
+        };
+
+        for (from, message) in messages {
+            if Some(from) != self.server {
+                continue;
+            }
+
+            match message {
+                Message::Welcome { id, level } => {
+                    // the level comes again with every respawn
+                    if self.level.is_none() && self.arrived.is_none() {
+                        self.arrived = Some(level);
+                    }
+
+                    self.id = Some(id);
+                    self.heard_at = Some(Instant::now());
+                },
+                Message::Snapshot(data) => {
+                    let timeline = &self.timeline;
+
+                    // deltas against a baseline that is gone are dropped, the
+                    // server falls back to a keyframe
+                    let snapshot = match wire::decode(&data, |tick| timeline.get(tick)) {
+                        Some(snapshot) => snapshot,
+                        None           => continue,
+                    };
+
+                    let ack = snapshot.ack;
+                    self.heard_at = Some(Instant::now());
+
+                    if self.timeline.push(snapshot, now) {
+                        self.reconcile(ack);
+                    }
+                },
+                _                           => {},
+            }
+        }
+
+        // the server dropped this client or went away
+        let silent = self.heard_at.map_or(false, |time| time.elapsed() > TIMEOUT);
+
+        if self.id.is_some() && silent {
+            println!("No snapshot from the server, joining again");
+            self.id = None;
+            self.joined_at = None;
+            self.heard_at = None;
+        }
+
+        let retry = self.joined_at.map_or(true, |time| time.elapsed() > JOIN_INTERVAL);
+
+        if self.id.is_none() && retry {
+            self.send(&Message::Join);
+            self.joined_at = Some(Instant::now());
+        }
+    }
+
+    // Corrects the prediction with the own drone of the newest snapshot
+    fn reconcile(&mut self, ack: u32) {
+        let dt = 1.0 / TICK_RATE as f32;
+
+        let state = match (self.id, self.timeline.latest()) {
+            (Some(id), Some(snapshot)) => snapshot.drones.iter().find(|state| state.id == id),
+            _                          => None,
+        };
+
+        let state = match state {
+            Some(state) => state.clone(),
+            // eliminated, the prediction starts again with the next drone
+            None        => {
+                self.prediction = None;
+                return;
+            },
+        };
+
+        match self.prediction.as_mut() {
+            Some(prediction) if prediction.id() == state.id => {
+                prediction.reconcile(ack, &state, dt);
+            },
+            _ => {
+                self.prediction = self.level.as_ref()
+                    .map(|(level, hulls)| Prediction::new(level, hulls.clone(), &state));
+            },
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.send(&Message::Leave);
+    }
+}
+
+// Sends the player controls to the server at the tick rate and flies the
+// predicted drone with them. While paused the drone keeps its heading and
+// the inputs keep the client from timing out.
+pub fn send(
+    input: Const<Input>,
+    camera: Const<Camera>,
+    frame: Const<Frame>,
+    state: Const<State>,
+    mut client: Mut<Client>,
+) {
+    if client.id.is_none() {
+        return;
+    }
+
+    let dt = 1.0 / TICK_RATE as f32;
+    let mut intent = if state.get::<Pause>().is_some() {
+        drone::Intent {
+            y_angle: camera.y_angle,
+            xz_angle: camera.xz_angle,
+            ..Default::default()
+        }
+    } else {
+        drone::Intent::from_input(&input, &camera)
+    };
+
+    client.release = client.release || intent.release;
+    client.accumulator = (client.accumulator + 1.0 / frame.fps()).min(MAX_CATCH_UP as f32 * dt);
+
+    while client.accumulator >= dt {
+        client.accumulator = client.accumulator - dt;
+        client.tick = client.tick.wrapping_add(1);
+
+        intent.release = client.release;
+        client.release = false;
+
+        let tick = client.tick;
+        let baseline = client.timeline.latest().map_or(0, |snapshot| snapshot.tick);
+
+        client.send(&Message::Input { tick, baseline, intent });
+
+        if let Some(prediction) = client.prediction.as_mut() {
+            prediction.step(tick, intent, dt);
+        }
+    }
+}
+
+// Mirrors the drones of the server snapshots
+pub fn receive(
+    mut world: Mut<World>,
+    mut assets: Mut<Assets>,
+    mut bodies: Mut<RigidBodySet>,
+    mut colliders: Mut<ColliderSet>,
+    hulls: Const<hull::Hulls>,
+    mut arena: Mut<arena::Arena>,
+    mut to_exile: Mut<ToExile>,
+    frame: Const<Frame>,
+    mut client: Mut<Client>,
+) {
+    // the system also runs in the pause menu of the offline games
+    if !client.is_online() {
+        return;
+    }
+
+    client.poll();
+
+    // the level of the server is spawned once it is known
+    if let Some(level) = client.arrived.take() {
+        arena::spawn(
+            &level,
+            &mut arena,
+            &mut world,
+            &mut assets,
+            &mut bodies,
+            &mut colliders,
+            &hulls,
+        );
+
+        client.prepare(level, hull::Hulls::clone(&hulls));
+    }
+
+    let now = client.clock.elapsed().as_secs_f32();
+    let mut states = client.timeline.sample(now);
+
+    // the own drone is shown where it was predicted
+    if let Some(prediction) = client.prediction.as_mut() {
+        prediction.smooth(1.0 / frame.fps());
+
+        if let Some(predicted) = prediction.state() {
+            if let Some(state) = states.iter_mut().find(|state| state.id == predicted.id) {
+                *state = predicted;
+            }
+        }
+    }
+
+    // spawn drones new to this client
+    for state in states.iter() {
+        if client.bodies.iter().any(|(id, _)| *id == state.id) {
+            continue;
+        }
+
+        let position = Point3::new(state.position[0], state.position[1], state.position[2]);
+
+        let body = drone::spawn(
+            &mut world,
+            &mut assets,
+            &mut bodies,
+            &mut colliders,
+            &hulls,
+            position,
+            drone::Stats::default(),
+        );
+
+        client.bodies.push((state.id, body));
+    }
+
+    let mut gone = Vec::new();
+
+    let query = world.query::<(
+        &Entity, &mut Transform, &mut RigidBodyHandle, &mut drone::Stats
+    )>();
+
+    for (entity, transform, rigid_body, stats) in query {
+        let id = client.bodies.iter()
+            .find(|(_, body)| body == rigid_body)
+            .map(|(id, _)| *id);
+
+        let state = id.and_then(|id| states.iter().find(|state| state.id == id));
+
+        let state = match state {
+            Some(state) => state,
+            None        => {
+                // despawned on the server
+                to_exile.entity_list.push(*entity);
+                gone.push(*rigid_body);
+                continue;
+            },
+        };
+
+        let body = bodies.get_mut(*rigid_body).unwrap();
+
+        body.set_position(isometry(state), false);
+        body.set_linvel(vector(state.velocity), false);
+        body.set_angvel(vector(state.spin), false);
+
+        // only the own drone is the player here, others look like bots
+        stats.is_player = client.id == Some(state.id);
+        stats.health = state.health;
+        stats.charge = state.charge;
+        stats.strike_charge = state.strike_charge;
+        stats.shield = state.shield;
+        stats.boost = state.boost;
+        stats.x = state.position[0];
+        stats.y = state.position[1];
+        stats.z = state.position[2];
+
+        drone::sync_transform(transform, body);
+    }
+
+    client.bodies.retain(|(_, body)| !gone.contains(body));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(id: u32, x: f32) -> DroneState {
+        DroneState {
+            id,
+            is_player: false,
+            position: [x, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            velocity: [x, 0.0, 0.0],
+            spin: [0.0, x, 0.0],
+            health: x,
+            charge: 0.0,
+            strike_charge: 0.0,
+            shield: 0.0,
+            boost: 0.0,
+        }
+    }
+
+    fn snapshot(tick: u32, drones: Vec<DroneState>) -> Snapshot {
+        Snapshot { tick, ack: 0, drones, beams: Vec::new() }
+    }
+
+    #[test]
+    fn messages_survive_encoding() {
+        let input = Message::Input {
+            tick: 42,
+            baseline: 39,
+            intent: drone::Intent { forward: true, y_angle: 1.5, ..Default::default() },
+        };
+        let snapshot = Message::Snapshot(vec![1, 42, 40, 0, 0, 0]);
+        let welcome = Message::Welcome {
+            id: 3,
+            level: arena::Level::load(arena::DEFAULT_LEVEL).unwrap(),
+        };
+
+        assert_eq!(decode(&encode(&input)), Some(input));
+        assert_eq!(decode(&encode(&snapshot)), Some(snapshot));
+        assert!(encode(&welcome).len() <= MAX_PACKET);
+        assert_eq!(decode(&encode(&welcome)), Some(welcome));
+        assert_eq!(decode(&[0xff, 0x01]), None);
+    }
+
+    #[test]
+    fn timeline_interpolates_between_snapshots() {
+        let mut timeline = Timeline::default();
+
+        timeline.push(snapshot(0, vec![state(1, 0.0)]), 0.0);
+        timeline.push(snapshot(12, vec![state(1, 10.0), state(2, 5.0)]), 0.2);
+
+        // 0.05s after the last snapshot arrived, rendering 0.1s in the past
+        let drones = timeline.sample(0.25);
+        let time = tick_time(12) + 0.05 - INTERPOLATION_DELAY;
+        let expected = 10.0 * time / tick_time(12);
+
+        assert_eq!(drones.len(), 2);
+        assert!((drones[0].position[0] - expected).abs() < 1e-4);
+        // stats are not interpolated
+        assert_eq!(drones[0].health, 10.0);
+        assert_eq!(drones[1].position[0], 5.0);
+    }
+
+    #[test]
+    fn timeline_drops_late_snapshots() {
+        let mut timeline = Timeline::default();
+
+        timeline.push(snapshot(10, vec![state(1, 1.0)]), 0.0);
+        timeline.push(snapshot(5, vec![state(1, 2.0)]), 0.1);
+
+        assert_eq!(timeline.snapshots.len(), 1);
+        assert_eq!(timeline.sample(10.0)[0].position[0], 1.0);
+    }
+
+    #[test]
+    fn silent_server_is_joined_again() {
+        let mut server = Link::bind("127.0.0.1:0").unwrap();
+        let mut client = Client::connect(server.local_addr().unwrap()).unwrap();
+
+        client.id = Some(3);
+        client.joined_at = Some(Instant::now());
+        client.heard_at = Instant::now().checked_sub(TIMEOUT + Duration::from_millis(10));
+
+        client.poll();
+        assert_eq!(client.id, None);
+
+        let mut joined = false;
+
+        for _ in 0..100 {
+            std::thread::sleep(Duration::from_millis(1));
+
+            if server.receive().iter().any(|(_, message)| *message == Message::Join) {
+                joined = true;
+                break;
+            }
+        }
+
+        assert!(joined);
+    }
+}
@@ -1,12 +1,17 @@
+use super::ToExile;
+
 use rapier3d:: {
     na::{ Vector3, },
-    dynamics::{ CCDSolver, JointSet, RigidBodySet, IntegrationParameters, },
+    dynamics::{
+        CCDSolver, JointSet, RigidBodySet, RigidBodyHandle, IntegrationParameters,
+    },
     geometry::{ BroadPhase, NarrowPhase, ColliderSet, },
-    pipeline::PhysicsPipeline,
+    pipeline::{ PhysicsPipeline, QueryPipeline, },
 };
 
 use dotrix::{
-    ecs::{ Mut, Context, Const },
+    ecs::{ Mut, Context, Const, Entity, },
+    services::{ World, },
     Frame,
 };
 
@@ -26,6 +31,62 @@ impl Default for Pipeline {
     }
 }
 
+impl Pipeline {
+    // Advances the simulation by `dt` seconds
+    pub fn run(
+        &mut self,
+        dt: f32,
+        bodies: &mut RigidBodySet,
+        colliders: &mut ColliderSet,
+        joints: &mut JointSet,
+        broad_phase: &mut BroadPhase,
+        narrow_phase: &mut NarrowPhase,
+        ccd_solver: &mut CCDSolver,
+        query_pipeline: &mut QueryPipeline,
+    ) {
+        let mut integration_parameters = self.integration_parameters;
+        let physics_hooks = ();
+        let event_handler = ();
+
+        integration_parameters.dt = dt;
+
+        self.pipeline.step(
+            &self.gravity,
+            &integration_parameters,
+            broad_phase,
+            narrow_phase,
+            bodies,
+            colliders,
+            joints,
+            ccd_solver,
+            &physics_hooks,
+            &event_handler
+        );
+
+        query_pipeline.update(bodies, colliders);
+    }
+}
+
+// Forces applied to a body during the last frame, kept for the debug draw
+pub struct Forces {
+    pub thrust: Vector3<f32>,
+    pub drag: Vector3<f32>,
+    pub gravity: Vector3<f32>,
+    // the last strike impulse, kept until the next strike
+    pub strike: Vector3<f32>,
+}
+
+impl Default for Forces {
+    fn default() -> Self {
+        Self {
+            thrust: Vector3::new(0.0, 0.0, 0.0),
+            drag: Vector3::new(0.0, 0.0, 0.0),
+            gravity: Vector3::new(0.0, 0.0, 0.0),
+            strike: Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
 pub fn step(mut context: Context<Pipeline>,
     mut bodies: Mut<RigidBodySet>,
     mut colliders: Mut<ColliderSet>,
@@ -33,29 +94,185 @@ pub fn step(mut context: Context<Pipeline>,
     mut broad_phase: Mut<BroadPhase>,
     mut narrow_phase: Mut<NarrowPhase>,
     mut ccd_solver: Mut<CCDSolver>,
+    mut query_pipeline: Mut<QueryPipeline>,
     frame: Const<Frame>,
 ) {
-
-    let gravity = context.gravity;
-    let mut integration_parameters = context.integration_parameters;
-    let physics_hooks = ();
-    let event_handler = ();
-
-
-
-    integration_parameters.dt = 1.0 / frame.fps();
-
-    context.pipeline.step(
-        &gravity,
-        &integration_parameters,
-        &mut broad_phase,
-        &mut narrow_phase,
+    context.run(
+        1.0 / frame.fps(),
         &mut bodies,
         &mut colliders,
         &mut joints,
+        &mut broad_phase,
+        &mut narrow_phase,
         &mut ccd_solver,
-        &physics_hooks,
-        &event_handler
+        &mut query_pipeline,
     );
+}
+
+// Removes rigid bodies together with their colliders and joints
+pub fn remove_bodies(
+    handles: &[RigidBodyHandle],
+    bodies: &mut RigidBodySet,
+    colliders: &mut ColliderSet,
+    joints: &mut JointSet,
+) {
+    for handle in handles {
+        bodies.remove(*handle, colliders, joints);
+    }
+}
+
+// Despawns entities listed in ToExile together with their physics
+pub fn exile(
+    mut world: Mut<World>,
+    mut to_exile: Mut<ToExile>,
+    mut bodies: Mut<RigidBodySet>,
+    mut colliders: Mut<ColliderSet>,
+    mut joints: Mut<JointSet>,
+) {
+    let entity_list = std::mem::take(&mut to_exile.entity_list);
+
+    exile_entities(&mut world, entity_list, &mut bodies, &mut colliders, &mut joints);
+}
+
+// Despawns the entities and removes their rigid bodies
+pub fn exile_entities(
+    world: &mut World,
+    list: Vec<Entity>,
+    bodies: &mut RigidBodySet,
+    colliders: &mut ColliderSet,
+    joints: &mut JointSet,
+) {
+    // the same entity could be listed more than once
+    let mut entity_list: Vec<Entity> = Vec::new();
+    for entity in list {
+        if !entity_list.contains(&entity) {
+            entity_list.push(entity);
+        }
+    }
+
+    let mut handles = Vec::new();
+
+    let query = world.query::<( &Entity, &mut RigidBodyHandle )>();
+
+    for (entity, rigid_body) in query {
+        if entity_list.contains(entity) {
+            handles.push(*rigid_body);
+        }
+    }
+
+    remove_bodies(&handles, bodies, colliders, joints);
+
+    for entity in entity_list {
+        world.exile(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ beam, drone, };
+
+    use dotrix::math::Point3 as Position;
+
+    use rapier3d::{
+        dynamics::{ BallJoint, BodyStatus, RigidBodyBuilder, },
+        geometry::{ ColliderBuilder, },
+        na::{ Point3, },
+    };
+
+    fn spawn_body(
+        bodies: &mut RigidBodySet,
+        colliders: &mut ColliderSet,
+        collider_count: usize,
+    ) -> RigidBodyHandle {
+        let handle = bodies.insert(RigidBodyBuilder::new(BodyStatus::Dynamic).build());
+
+        for _ in 0..collider_count {
+            colliders.insert(ColliderBuilder::ball(1.0).build(), handle, bodies);
+        }
+
+        handle
+    }
+
+    #[test]
+    fn removing_bodies_leaves_no_dangling_handles() {
+        let mut bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+        let mut joints = JointSet::new();
+
+        let first = spawn_body(&mut bodies, &mut colliders, 2);
+        let second = spawn_body(&mut bodies, &mut colliders, 1);
+
+        joints.insert(
+            &mut bodies,
+            first,
+            second,
+            BallJoint::new(Point3::origin(), Point3::origin()),
+        );
+
+        remove_bodies(&[first], &mut bodies, &mut colliders, &mut joints);
+
+        assert!(bodies.get(first).is_none());
+        assert_eq!(bodies.len(), 1);
+        assert_eq!(colliders.len(), 1);
+        assert_eq!(joints.len(), 0);
+        assert!(colliders.iter().all(|(_, collider)| collider.parent() == second));
+
+        // removing the same body twice is harmless
+        remove_bodies(&[first, second], &mut bodies, &mut colliders, &mut joints);
+
+        assert_eq!(bodies.len(), 0);
+        assert_eq!(colliders.len(), 0);
+        assert_eq!(joints.len(), 0);
+    }
+
+    #[test]
+    fn exiled_entities_leave_no_dangling_handles() {
+        let mut world = World::new();
+        let mut bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+        let mut joints = JointSet::new();
 
+        let beam_body = beam::insert_body(
+            &mut bodies, &mut colliders, None, Position::new(0.0, 0.0, 0.0),
+        );
+        world.spawn(Some((beam_body, beam::Stats::default())));
+
+        let drone_body = drone::insert_body(
+            &mut bodies, &mut colliders, None, Position::new(10.0, 0.0, 0.0),
+        );
+        world.spawn(Some((drone_body, drone::Stats::default())));
+
+        // a body held to the drone by a joint
+        let anchor = spawn_body(&mut bodies, &mut colliders, 1);
+        joints.insert(
+            &mut bodies,
+            drone_body,
+            anchor,
+            BallJoint::new(Point3::origin(), Point3::origin()),
+        );
+        world.spawn(Some((anchor,)));
+
+        let entities: Vec<Entity> = world.query::<( &Entity, &mut RigidBodyHandle )>()
+            .map(|(entity, _)| *entity)
+            .collect();
+        assert_eq!(entities.len(), 3);
+
+        // listed twice, as when two systems exile the same drone
+        let mut list = entities.clone();
+        list.extend(entities.iter().copied());
+
+        exile_entities(&mut world, list, &mut bodies, &mut colliders, &mut joints);
+
+        assert_eq!(bodies.len(), 0);
+        assert_eq!(colliders.len(), 0);
+        assert_eq!(joints.len(), 0);
+        assert_eq!(world.query::<( &Entity, &mut RigidBodyHandle )>().count(), 0);
+
+        // exiling them again is harmless
+        exile_entities(&mut world, entities, &mut bodies, &mut colliders, &mut joints);
+
+        assert_eq!(bodies.len(), 0);
+        assert_eq!(world.query::<( &Entity, )>().count(), 0);
+    }
 }
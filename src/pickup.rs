@@ -0,0 +1,300 @@
+use super::{ drone, ToExile, };
+
+use rand::Rng;
+
+use rapier3d::{
+    dynamics::{ RigidBodyBuilder, BodyStatus, RigidBodySet, RigidBodyHandle, },
+    geometry::{ ColliderSet, ColliderBuilder, NarrowPhase, },
+};
+
+use dotrix::{
+    Frame,
+    Transform,
+    Pipeline,
+    pbr:: { Model, Material, },
+    services::{ Assets, World, },
+    math::{ Point3, Vec3, Quat, },
+    ecs::{ Mut, Const, Entity, },
+};
+
+const RADIUS: f32 = 1.5;
+
+const ENERGY_CHARGE: f32 = 25.0;
+const REPAIR_HEALTH: f32 = 30.0;
+// seconds of the doubled thrust
+const BOOST_TIME:    f32 = 10.0;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Kind {
+    // restores charge
+    Energy,
+    // restores health
+    Repair,
+    // temporary thrust boost
+    Boost,
+}
+
+pub struct Pickup {
+    pub kind: Kind,
+}
+
+// Spawns pickups at random points of the arena
+pub struct Spawner {
+    // seconds between pickups
+    pub interval: f32,
+    pub max_count: usize,
+    // pickups appear inside the sphere around the arena center
+    pub radius: f32,
+    countdown: f32,
+}
+
+impl Default for Spawner {
+    fn default() -> Self {
+        Self {
+            interval: 10.0,
+            max_count: 8,
+            radius: 100.0,
+            countdown: 10.0,
+        }
+    }
+}
+
+impl Spawner {
+    pub fn reset(&mut self) {
+        self.countdown = self.interval;
+    }
+}
+
+// Applies the pickup effect to the drone that collected it
+pub fn apply(kind: Kind, stats: &mut drone::Stats) {
+    match kind {
+        Kind::Energy => {
+            stats.charge = stats.charge + ENERGY_CHARGE;
+            drone::clamp_charge(stats);
+        },
+        Kind::Repair => {
+            stats.health = (stats.health + REPAIR_HEALTH).min(100.0);
+        },
+        Kind::Boost => {
+            stats.boost = BOOST_TIME;
+        },
+    }
+}
+
+pub fn spawn(
+    world: &mut World,
+    assets: &mut Assets,
+    bodies: &mut RigidBodySet,
+    colliders: &mut ColliderSet,
+    position: Point3,
+    kind: Kind,
+) {
+    let texture = assets.register("energy_beam::texture");
+    let mesh = assets.register("energy_beam::mesh");
+
+    let rigid_body = RigidBodyBuilder::new(BodyStatus::Static)
+        .translation(position.x, position.y, position.z)
+        .build();
+
+    // sensors detect drones without pushing them
+    let collider = ColliderBuilder::ball(RADIUS)
+        .sensor(true)
+        .build();
+
+    let body_handle = bodies.insert(rigid_body);
+
+    colliders.insert(collider, body_handle, bodies);
+
+    let scale = match kind {
+        Kind::Energy => 0.2,
+        Kind::Repair => 0.25,
+        Kind::Boost  => 0.15,
+    };
+
+    world.spawn(Some((
+        Model::from(mesh),
+        Material {
+            texture,
+            ..Default::default()
+        },
+        Transform {
+            translate: Vec3::new(position.x, position.y, position.z),
+            scale: Vec3::new(scale, scale, scale),
+            ..Default::default()
+        },
+        body_handle,
+        Pickup { kind },
+        Pipeline::default(),
+    )));
+}
+
+pub fn spawner(
+    mut world: Mut<World>,
+    mut assets: Mut<Assets>,
+    mut bodies: Mut<RigidBodySet>,
+    mut colliders: Mut<ColliderSet>,
+    frame: Const<Frame>,
+    mut spawner: Mut<Spawner>,
+) {
+    spawner.countdown = spawner.countdown - 1.0 / frame.fps();
+
+    if spawner.countdown > 0.0 {
+        return;
+    }
+
+    spawner.countdown = spawner.interval;
+
+    let count = world.query::<( &Pickup, )>().count();
+
+    if count >= spawner.max_count {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+
+    // random point inside the sphere
+    let position = loop {
+        let point = Vec3::new(
+            rng.gen_range(-1.0..=1.0),
+            rng.gen_range(-1.0..=1.0),
+            rng.gen_range(-1.0..=1.0),
+        );
+
+        if point.x * point.x + point.y * point.y + point.z * point.z <= 1.0 {
+            break point * spawner.radius;
+        }
+    };
+
+    let kind = match rng.gen_range(0..3) {
+        0 => Kind::Energy,
+        1 => Kind::Repair,
+        _ => Kind::Boost,
+    };
+
+    spawn(
+        &mut world,
+        &mut assets,
+        &mut bodies,
+        &mut colliders,
+        Point3::new(position.x, position.y, position.z),
+        kind,
+    );
+}
+
+pub fn collect(
+    world: Const<World>,
+    bodies: Const<RigidBodySet>,
+    colliders: Const<ColliderSet>,
+    narrow_phase: Const<NarrowPhase>,
+    frame: Const<Frame>,
+    mut to_exile: Mut<ToExile>,
+) {
+    let query = world.query::<(
+        &Entity, &mut Transform, &mut RigidBodyHandle, &mut Pickup
+    )>();
+
+    for (entity, transform, rigid_body, pickup) in query {
+        // spin the pickup model
+        transform.rotate = transform.rotate *
+            Quat::new((0.5 / frame.fps()).cos(), 0.0, (0.5 / frame.fps()).sin(), 0.0);
+
+        let collider = match bodies.get(*rigid_body).and_then(|body| body.colliders().first()) {
+            Some(collider) => *collider,
+            None           => continue,
+        };
+
+        let intersections = match narrow_phase.intersections_with(collider) {
+            Some(intersections) => intersections,
+            None                => continue,
+        };
+
+        // bodies of the drones touching the pickup
+        let touching: Vec<RigidBodyHandle> = intersections
+            .filter(|(_, _, intersecting)| *intersecting)
+            .map(|(collider1, collider2, _)| {
+                let other = if collider1 == collider { collider2 } else { collider1 };
+                colliders.get(other).unwrap().parent()
+            })
+            .collect();
+
+        if touching.is_empty() {
+            continue;
+        }
+
+        let drones = world.query::<( &mut RigidBodyHandle, &mut drone::Stats )>();
+
+        for (drone_body, stats) in drones {
+            if touching.contains(drone_body) {
+                apply(pickup.kind, stats);
+                to_exile.entity_list.push(*entity);
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn energy_charges_up_to_max() {
+        let mut stats = drone::Stats { charge: drone::MAX_CHARGE - 5.0, ..Default::default() };
+
+        apply(Kind::Energy, &mut stats);
+
+        assert_eq!(stats.charge, drone::MAX_CHARGE);
+    }
+
+    #[test]
+    fn repair_heals_up_to_full_health() {
+        let mut stats = drone::Stats { health: 50.0, ..Default::default() };
+
+        apply(Kind::Repair, &mut stats);
+        assert_eq!(stats.health, 50.0 + REPAIR_HEALTH);
+
+        apply(Kind::Repair, &mut stats);
+        assert_eq!(stats.health, 100.0);
+    }
+
+    #[test]
+    fn boost_doubles_thrust_while_active() {
+        let mut stats = drone::Stats::default();
+        assert_eq!(drone::boost_factor(&stats), 1.0);
+
+        apply(Kind::Boost, &mut stats);
+
+        assert_eq!(stats.boost, BOOST_TIME);
+        assert!(drone::boost_factor(&stats) > 1.0);
+    }
+
+    #[test]
+    fn boost_lasts_the_same_time_at_any_frame_rate() {
+        use crate::physics;
+
+        for fps in [30.0, 144.0].iter() {
+            let mut world = World::new();
+            let mut bodies = RigidBodySet::new();
+            let mut colliders = ColliderSet::new();
+            let mut to_exile = ToExile::default();
+
+            let mut stats = drone::Stats::default();
+            apply(Kind::Boost, &mut stats);
+
+            let body = drone::insert_body(&mut bodies, &mut colliders, None, Point3::new(0.0, 0.0, 0.0));
+            world.spawn(Some((Transform::default(), body, stats, physics::Forces::default())));
+
+            // a second short of the boost time
+            for _ in 0..((BOOST_TIME - 1.0) * fps) as usize {
+                drone::update(&world, &mut bodies, false, 1.0 / fps, &mut to_exile);
+            }
+
+            let boost: Vec<f32> = world.query::<( &drone::Stats, )>()
+                .map(|(stats,)| stats.boost)
+                .collect();
+
+            assert_eq!(boost.len(), 1);
+            assert!((boost[0] - 1.0).abs() < 0.05);
+        }
+    }
+}
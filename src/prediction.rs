@@ -0,0 +1,232 @@
+use super::{ arena, drone, hull, net, server };
+
+use rapier3d::{
+    dynamics::{ RigidBodyHandle, },
+    na::{ Vector3, },
+};
+
+use std::collections::VecDeque;
+
+// inputs kept for the replay, about two seconds of ticks
+const MAX_INPUTS: usize = 128;
+// rate the correction offset fades out, per second
+const SMOOTHING: f32 = 10.0;
+// corrections larger than this are applied at once
+const SNAP_DISTANCE: f32 = 5.0;
+
+// Copy of the player drone flown ahead of the server with the same flight
+// model, corrected by every snapshot
+pub struct Prediction {
+    simulation: server::Simulation,
+    id: u32,
+    body: RigidBodyHandle,
+    // inputs not acknowledged by the server yet
+    inputs: VecDeque<(u32, drone::Intent)>,
+    // visual offset left by the last correction
+    error: Vector3<f32>,
+}
+
+impl Prediction {
+    // Starts predicting the drone from its state on the server, the level
+    // provides the beams and obstacles the drone interacts with
+    pub fn new(level: &arena::Level, hulls: hull::Hulls, state: &net::DroneState) -> Self {
+        let mut simulation = server::Simulation::new(level, hulls);
+
+        let body = simulation.insert_drone(
+            state.id,
+            dotrix::math::Point3::new(state.position[0], state.position[1], state.position[2]),
+            drone::Stats { is_player: true, ..Default::default() },
+        );
+
+        simulation.set_drone(body, state);
+
+        Self {
+            simulation,
+            id: state.id,
+            body,
+            inputs: VecDeque::new(),
+            error: Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    // Flies the drone one tick with the input sent to the server
+    pub fn step(&mut self, tick: u32, intent: drone::Intent, dt: f32) {
+        self.inputs.push_back((tick, intent));
+
+        while self.inputs.len() > MAX_INPUTS {
+            self.inputs.pop_front();
+        }
+
+        self.simulation.tick(dt, &[(self.body, intent)]);
+    }
+
+    // Rewinds the drone to the server state after the input `ack` and
+    // replays the inputs the server hasn't applied yet
+    pub fn reconcile(&mut self, ack: u32, state: &net::DroneState, dt: f32) {
+        let before = self.position();
+
+        while self.inputs.front().map_or(false, |(tick, _)| *tick <= ack) {
+            self.inputs.pop_front();
+        }
+
+        self.simulation.set_drone(self.body, state);
+
+        for (_, intent) in self.inputs.iter() {
+            self.simulation.tick(dt, &[(self.body, *intent)]);
+        }
+
+        if let (Some(before), Some(after)) = (before, self.position()) {
+            self.error = self.error + before - after;
+        }
+
+        if self.error.norm() > SNAP_DISTANCE {
+            self.error = Vector3::new(0.0, 0.0, 0.0);
+        }
+    }
+
+    // Fades the correction offset out
+    pub fn smooth(&mut self, dt: f32) {
+        self.error = self.error * (-SMOOTHING * dt).exp();
+    }
+
+    // Predicted state with the correction offset, None if the drone was
+    // eliminated in the prediction
+    pub fn state(&self) -> Option<net::DroneState> {
+        let mut state = self.simulation.snapshot(0).drones.into_iter()
+            .find(|state| state.id == self.id)?;
+
+        state.position = [
+            state.position[0] + self.error.x,
+            state.position[1] + self.error.y,
+            state.position[2] + self.error.z,
+        ];
+
+        Some(state)
+    }
+
+    fn position(&self) -> Option<Vector3<f32>> {
+        self.simulation.bodies.get(self.body)
+            .map(|body| body.position().translation.vector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / net::TICK_RATE as f32;
+
+    fn level() -> arena::Level {
+        arena::Level::default()
+    }
+
+    fn start() -> net::DroneState {
+        net::DroneState {
+            id: 7,
+            is_player: true,
+            position: [10.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            velocity: [0.0, 0.0, 0.0],
+            spin: [0.0, 0.0, 0.0],
+            health: 100.0,
+            charge: 100.0,
+            strike_charge: 0.0,
+            shield: 0.0,
+            boost: 0.0,
+        }
+    }
+
+    fn forward() -> drone::Intent {
+        drone::Intent { forward: true, accelerate: true, ..Default::default() }
+    }
+
+    #[test]
+    fn prediction_matches_the_server_flight() {
+        // the authority flies the same drone with the same inputs
+        let mut server = server::Simulation::new(&level(), hull::Hulls::default());
+        let body = server.insert_drone(7, dotrix::math::Point3::new(10.0, 0.0, 0.0),
+            drone::Stats { is_player: true, ..Default::default() });
+        server.set_drone(body, &start());
+
+        let mut prediction = Prediction::new(&level(), hull::Hulls::default(), &start());
+
+        for tick in 1..=60 {
+            server.tick(DT, &[(body, forward())]);
+            prediction.step(tick, forward(), DT);
+        }
+
+        let expected = server.snapshot(0).drones[0].position;
+        let predicted = prediction.state().unwrap().position;
+
+        for i in 0..3 {
+            assert!((expected[i] - predicted[i]).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn reconciliation_replays_unacknowledged_inputs() {
+        let mut prediction = Prediction::new(&level(), hull::Hulls::default(), &start());
+
+        for tick in 1..=30 {
+            prediction.step(tick, forward(), DT);
+        }
+
+        let predicted = prediction.position().unwrap();
+
+        // the server confirms the start state after the first 10 inputs were
+        // applied somewhere else, e.g. the drone was pushed back
+        prediction.reconcile(10, &start(), DT);
+
+        assert_eq!(prediction.inputs.len(), 20);
+        assert_eq!(prediction.inputs.front().unwrap().0, 11);
+
+        // the replayed drone is behind the first prediction
+        let replayed = prediction.position().unwrap();
+        let origin = Vector3::new(10.0, 0.0, 0.0);
+
+        assert!((replayed - origin).norm() < (predicted - origin).norm());
+    }
+
+    #[test]
+    fn small_corrections_fade_and_large_ones_snap() {
+        let mut prediction = Prediction::new(&level(), hull::Hulls::default(), &start());
+
+        for tick in 1..=30 {
+            prediction.step(tick, forward(), DT);
+        }
+
+        // the server acknowledges every input with the drone a bit aside
+        let predicted = prediction.state().unwrap();
+        let mut corrected = predicted.clone();
+        corrected.position[1] = corrected.position[1] + SNAP_DISTANCE / 2.0;
+
+        prediction.reconcile(30, &corrected, DT);
+
+        // the drone is still drawn where it was
+        let drawn = prediction.state().unwrap().position;
+        for i in 0..3 {
+            assert!((drawn[i] - predicted.position[i]).abs() < 1e-3);
+        }
+
+        for _ in 0..120 {
+            prediction.smooth(DT);
+        }
+
+        assert!(prediction.error.norm() < 1e-3);
+
+        // too far off the drone jumps to the server state
+        let mut corrected = prediction.state().unwrap();
+        corrected.position[1] = corrected.position[1] + SNAP_DISTANCE * 2.0;
+
+        prediction.reconcile(30, &corrected, DT);
+
+        let drawn = prediction.state().unwrap().position;
+        for i in 0..3 {
+            assert!((drawn[i] - corrected.position[i]).abs() < 1e-3);
+        }
+    }
+}
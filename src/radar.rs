@@ -0,0 +1,170 @@
+use super::{ drone, beam, settings };
+
+use rapier3d::{
+    dynamics::{ RigidBodySet, RigidBodyHandle, },
+    na::{ Vector3, },
+};
+
+use dotrix::ecs::{ Const, };
+use dotrix::services::{ World };
+use dotrix::overlay::Overlay;
+
+use dotrix::egui::{
+    self,
+    Egui,
+};
+
+// radar widget size in points
+const SIZE: f32 = 160.0;
+const BLIP_RADIUS: f32 = 3.0;
+
+// Position of an object on the radar plane relative to the player heading
+// (x - to the right, y - forward) in range units, and its relative altitude
+pub fn project(
+    offset: Vector3<f32>,
+    fwd: Vector3<f32>,
+    side: Vector3<f32>,
+    range: f32,
+) -> (f32, f32, f32) {
+    // project heading on the horizontal plane, so pitching doesn't rotate
+    // the radar
+    let fwd = Vector3::new(fwd.x, 0.0, fwd.z);
+    let fwd = if fwd.norm() > 0.0 { fwd.normalize() } else { fwd };
+
+    let x = -offset.dot(&side) / range;
+    let y = offset.dot(&fwd) / range;
+    let altitude = offset.y / range;
+
+    (x, y, altitude)
+}
+
+pub fn update(
+    world: Const<World>,
+    overlay: Const<Overlay>,
+    bodies: Const<RigidBodySet>,
+    settings: Const<settings::Settings>,
+) {
+    if !settings.show_radar {
+        return;
+    }
+
+    // find the player to center the radar on
+    let mut player = None;
+
+    let query = world.query::<( &mut RigidBodyHandle, &mut drone::Stats )>();
+
+    for (rigid_body, stats) in query {
+        if stats.is_primary() {
+            player = bodies.get(*rigid_body);
+        }
+    }
+
+    let player = match player {
+        Some(body) => body,
+        None       => return,
+    };
+
+    let origin = player.position().translation.vector;
+    let (fwd, side) = drone::axes(&drone::rotation(player));
+
+    let egui = overlay.get::<Egui>()
+        .expect("Renderer does not contain an Overlay instance");
+
+    let radar_ui_frame = egui::containers::Frame{
+        fill: egui::Color32::from_black_alpha(192),
+        corner_radius: 2.5,
+        margin: egui::Vec2::new(4.0, 4.0),
+        ..Default::default()
+    };
+
+    let margin = 8.0;
+
+    egui::containers::Window::new("radar")
+        .anchor(egui::Align2::RIGHT_TOP, egui::Vec2::new(-margin, margin))
+        .collapsible(false)
+        .title_bar(false)
+        .resizable(false)
+        .frame(radar_ui_frame)
+        .show(&egui.ctx, |ui| {
+            let (rect, _) = ui.allocate_exact_size(
+                egui::Vec2::new(SIZE, SIZE),
+                egui::Sense::hover(),
+            );
+
+            let painter = ui.painter();
+            let center = rect.center();
+            let radius = SIZE / 2.0 - BLIP_RADIUS;
+
+            let grid = egui::Stroke::new(1.0, egui::Color32::from_gray(64));
+            painter.circle_stroke(center, radius, grid);
+            painter.circle_stroke(center, radius / 2.0, grid);
+            painter.line_segment(
+                [center - egui::Vec2::new(radius, 0.0), center + egui::Vec2::new(radius, 0.0)],
+                grid,
+            );
+            painter.line_segment(
+                [center - egui::Vec2::new(0.0, radius), center + egui::Vec2::new(0.0, radius)],
+                grid,
+            );
+
+            // draws a blip with an altitude stem, objects out of range stick
+            // to the rim of the radar
+            let blip = |offset: Vector3<f32>, size: f32, color: egui::Color32| {
+                let (x, y, altitude) = project(offset, fwd, side, settings.radar_range);
+
+                let distance = (x * x + y * y).sqrt();
+                let (x, y, size) = if distance > 1.0 {
+                    (x / distance, y / distance, size * 0.5)
+                } else {
+                    (x, y, size)
+                };
+
+                let base = center + egui::Vec2::new(x * radius, -y * radius);
+                let top = base - egui::Vec2::new(0.0, altitude.max(-1.0).min(1.0) * radius / 2.0);
+
+                painter.line_segment([base, top], egui::Stroke::new(1.0, color));
+                painter.circle_filled(top, size, color);
+            };
+
+            for (rigid_body, _) in world.query::<( &mut RigidBodyHandle, &mut beam::Stats )>() {
+                if let Some(body) = bodies.get(*rigid_body) {
+                    let offset = body.position().translation.vector - origin;
+                    blip(offset, BLIP_RADIUS * 2.0, egui::Color32::from_rgb(64, 192, 255));
+                }
+            }
+
+            for (rigid_body, stats) in world.query::<( &mut RigidBodyHandle, &mut drone::Stats )>() {
+                if stats.is_primary() {
+                    continue;
+                }
+
+                // other local players are told apart from the bots
+                let color = if stats.is_player {
+                    egui::Color32::from_rgb(64, 255, 64)
+                } else {
+                    egui::Color32::from_rgb(255, 64, 64)
+                };
+
+                if let Some(body) = bodies.get(*rigid_body) {
+                    let offset = body.position().translation.vector - origin;
+                    blip(offset, BLIP_RADIUS, color);
+                }
+            }
+
+            // the player is always in the center, heading up
+            let player_color = egui::Color32::WHITE;
+            let stroke = egui::Stroke::new(1.5, player_color);
+            let nose = center - egui::Vec2::new(0.0, BLIP_RADIUS * 2.0);
+            let left = center + egui::Vec2::new(-BLIP_RADIUS * 1.5, BLIP_RADIUS * 1.5);
+            let right = center + egui::Vec2::new(BLIP_RADIUS * 1.5, BLIP_RADIUS * 1.5);
+            painter.line_segment([nose, left], stroke);
+            painter.line_segment([left, right], stroke);
+            painter.line_segment([right, nose], stroke);
+
+            ui.add(
+                egui::Label::new(format!("RANGE {:.0}", settings.radar_range))
+                    .text_color(egui::Color32::LIGHT_GRAY)
+                    .small()
+            );
+        });
+}
@@ -0,0 +1,765 @@
+use super::{ arena, beam, drone, hull, net, physics, wire, ToExile };
+
+use rapier3d::{
+    dynamics::{ CCDSolver, JointSet, RigidBodySet, RigidBodyHandle, },
+    geometry::{ BroadPhase, NarrowPhase, ColliderSet, },
+    pipeline::QueryPipeline,
+};
+
+use dotrix::{
+    Transform,
+    services::{ World, },
+    math::{ Point3, },
+    ecs::{ Entity, },
+};
+
+use rand::Rng;
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::{ SocketAddr, ToSocketAddrs, };
+use std::sync::{ Arc, atomic::{ AtomicBool, Ordering, }, mpsc, };
+use std::thread::{ self, JoinHandle, };
+use std::time::{ Duration, Instant, };
+
+// seconds before an eliminated player is back
+const RESPAWN_DELAY: f32 = 3.0;
+// bots and players appear on a shell around the origin
+const SPAWN_RADIUS: f32 = 60.0;
+// inputs queued per peer, older ones are dropped to bound the delay
+const MAX_QUEUED_INPUTS: usize = 8;
+// snapshots kept as the baselines of the deltas, as many as a client keeps
+const HISTORY_LENGTH: usize = 32;
+
+// Headless game world running the same systems as the local game
+pub struct Simulation {
+    pub world: World,
+    pipeline: physics::Pipeline,
+    pub bodies: RigidBodySet,
+    colliders: ColliderSet,
+    joints: JointSet,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    ccd_solver: CCDSolver,
+    query_pipeline: QueryPipeline,
+    contacts: drone::Contacts,
+    to_exile: ToExile,
+    hulls: hull::Hulls,
+    // sent to the clients joining
+    level: arena::Level,
+    next_id: u32,
+}
+
+impl Simulation {
+    pub fn new(level: &arena::Level, hulls: hull::Hulls) -> Self {
+        let mut simulation = Self {
+            world: World::new(),
+            pipeline: physics::Pipeline::default(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            joints: JointSet::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            ccd_solver: CCDSolver::new(),
+            query_pipeline: QueryPipeline::new(),
+            contacts: drone::Contacts::default(),
+            to_exile: ToExile::default(),
+            hulls,
+            level: level.clone(),
+            next_id: 1,
+        };
+
+        for (x, y, z) in level.beams.iter() {
+            let body = beam::insert_body(
+                &mut simulation.bodies,
+                &mut simulation.colliders,
+                simulation.hulls.get("energy_beam"),
+                Point3::new(*x, *y, *z),
+            );
+
+            let id = simulation.next_id;
+            simulation.next_id = simulation.next_id + 1;
+
+            simulation.world.spawn(Some((body, beam::Stats::default(), net::NetId(id))));
+        }
+
+        for obstacle in level.obstacles.iter() {
+            arena::insert_body(obstacle, &mut simulation.bodies, &mut simulation.colliders);
+        }
+
+        simulation
+    }
+
+    // Adds a drone at a random point of the spawn shell
+    pub fn spawn_drone(&mut self, stats: drone::Stats) -> (u32, RigidBodyHandle) {
+        let mut rng = rand::thread_rng();
+
+        let position = Point3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        let scale = SPAWN_RADIUS / (position.x.powi(2) + position.y.powi(2) + position.z.powi(2))
+            .sqrt()
+            .max(0.01);
+
+        let id = self.next_id;
+        self.next_id = self.next_id + 1;
+
+        let position = Point3::new(position.x * scale, position.y * scale, position.z * scale);
+
+        (id, self.insert_drone(id, position, stats))
+    }
+
+    // Adds a drone with the given network id
+    pub fn insert_drone(&mut self, id: u32, position: Point3, stats: drone::Stats) -> RigidBodyHandle {
+        let body = drone::insert_body(
+            &mut self.bodies,
+            &mut self.colliders,
+            self.hulls.get("drone"),
+            position,
+        );
+
+        self.world.spawn(Some((
+            Transform::default(),
+            body,
+            stats,
+            physics::Forces::default(),
+            net::NetId(id),
+        )));
+
+        body
+    }
+
+    // Moves the drone to the state received from the server
+    pub fn set_drone(&mut self, body: RigidBodyHandle, state: &net::DroneState) {
+        if let Some(rigid_body) = self.bodies.get_mut(body) {
+            rigid_body.set_position(net::isometry(state), true);
+            rigid_body.set_linvel(net::vector(state.velocity), true);
+            rigid_body.set_angvel(net::vector(state.spin), true);
+        }
+
+        let query = self.world.query::<( &mut RigidBodyHandle, &mut drone::Stats )>();
+
+        for (rigid_body, stats) in query {
+            if *rigid_body == body {
+                stats.health = state.health;
+                stats.charge = state.charge;
+                stats.strike_charge = state.strike_charge;
+                stats.shield = state.shield;
+                stats.boost = state.boost;
+            }
+        }
+    }
+
+    pub fn contains(&self, body: RigidBodyHandle) -> bool {
+        self.bodies.get(body).is_some()
+    }
+
+    pub fn despawn(&mut self, body: RigidBodyHandle) {
+        let query = self.world.query::<( &Entity, &mut RigidBodyHandle )>();
+
+        for (entity, rigid_body) in query {
+            if *rigid_body == body {
+                self.to_exile.entity_list.push(*entity);
+            }
+        }
+    }
+
+    // Advances the world by `dt` flying the player drones by their intents
+    pub fn tick(&mut self, dt: f32, intents: &[(RigidBodyHandle, drone::Intent)]) {
+        self.pipeline.run(
+            dt,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.joints,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.ccd_solver,
+            &mut self.query_pipeline,
+        );
+
+        let query = self.world.query::<(
+            &mut RigidBodyHandle, &mut drone::Stats, &mut physics::Forces
+        )>();
+
+        for (rigid_body, stats, forces) in query {
+            if let Some((_, intent)) = intents.iter().find(|(body, _)| body == rigid_body) {
+                let body = self.bodies.get_mut(*rigid_body).unwrap();
+                drone::fly(body, stats, forces, intent, None);
+            }
+        }
+
+        drone::update(&self.world, &mut self.bodies, false, dt, &mut self.to_exile);
+        drone::apply_collisions(
+            &self.world, &self.bodies, &self.colliders, &self.narrow_phase, &mut self.contacts,
+        );
+        beam::apply_gravity(&self.world, &mut self.bodies, &self.colliders, &self.query_pipeline);
+
+        if let Some(radius) = self.level.boundary {
+            arena::confine_bodies(&mut self.bodies, radius);
+        }
+
+        physics::exile_entities(
+            &mut self.world,
+            std::mem::take(&mut self.to_exile.entity_list),
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.joints,
+        );
+    }
+
+    pub fn snapshot(&self, tick: u32) -> net::Snapshot {
+        let mut drones = Vec::new();
+
+        let query = self.world.query::<(
+            &net::NetId, &mut RigidBodyHandle, &mut drone::Stats
+        )>();
+
+        for (id, rigid_body, stats) in query {
+            let body = self.bodies.get(*rigid_body).unwrap();
+            let position = body.position().translation.vector;
+            let velocity = body.linvel();
+            let spin = body.angvel();
+
+            drones.push(net::DroneState {
+                id: id.0,
+                is_player: stats.is_player,
+                position: [position.x, position.y, position.z],
+                rotation: net::from_quaternion(&body.position().rotation),
+                velocity: [velocity.x, velocity.y, velocity.z],
+                spin: [spin.x, spin.y, spin.z],
+                health: stats.health,
+                charge: stats.charge,
+                strike_charge: stats.strike_charge,
+                shield: stats.shield,
+                boost: stats.boost,
+            });
+        }
+
+        let mut beams = Vec::new();
+
+        let query = self.world.query::<( &net::NetId, &mut RigidBodyHandle, &mut beam::Stats )>();
+
+        for (id, rigid_body, stats) in query {
+            let position = self.bodies.get(*rigid_body).unwrap().position().translation.vector;
+
+            beams.push(net::BeamState {
+                id: id.0,
+                position: [position.x, position.y, position.z],
+                stats: stats.clone(),
+            });
+        }
+
+        net::Snapshot { tick, ack: 0, drones, beams }
+    }
+}
+
+// Client connected to the server
+struct Peer {
+    addr: SocketAddr,
+    // drone id and body, None while waiting for the respawn
+    drone: Option<(u32, RigidBodyHandle)>,
+    respawn_in: f32,
+    // inputs waiting for their tick, one is applied per tick
+    inputs: VecDeque<(u32, drone::Intent)>,
+    intent: drone::Intent,
+    // client tick of the last applied input
+    ack: u32,
+    // tick of the newest snapshot the client received
+    baseline: u32,
+    last_seen: Instant,
+}
+
+pub struct Server {
+    link: net::Link,
+    pub simulation: Simulation,
+    peers: Vec<Peer>,
+    tick: u32,
+    // snapshots sent lately, quantised as the clients decode them
+    history: VecDeque<net::Snapshot>,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A, simulation: Simulation) -> io::Result<Self> {
+        Ok(Self {
+            link: net::Link::bind(addr)?,
+            simulation,
+            peers: Vec::new(),
+            tick: 0,
+            history: VecDeque::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.link.local_addr()
+    }
+
+    // Delays and drops the packets to the clients
+    pub fn simulate(&mut self, conditions: net::Conditions) {
+        self.link.simulate(conditions);
+    }
+
+    pub fn players(&self) -> usize {
+        self.peers.len()
+    }
+
+    fn welcome(&self, id: u32) -> net::Message {
+        net::Message::Welcome { id, level: self.simulation.level.clone() }
+    }
+
+    // Ticks at the tick rate until `stop` is set
+    pub fn run(&mut self, stop: &AtomicBool) {
+        let period = Duration::from_secs(1) / net::TICK_RATE;
+        let dt = 1.0 / net::TICK_RATE as f32;
+        let mut next = Instant::now();
+
+        while !stop.load(Ordering::Relaxed) {
+            self.update(dt);
+
+            next = next + period;
+            let now = Instant::now();
+
+            if next > now {
+                thread::sleep(next - now);
+            } else {
+                // can't keep up, don't try to catch up with a burst of ticks
+                next = now;
+            }
+        }
+    }
+
+    // Handles the received messages, advances the simulation and sends the
+    // snapshots
+    pub fn update(&mut self, dt: f32) {
+        for (from, message) in self.link.receive() {
+            self.handle(from, message);
+        }
+
+        // drop silent peers
+        let mut lost = Vec::new();
+
+        self.peers.retain(|peer| {
+            let alive = peer.last_seen.elapsed() < net::TIMEOUT;
+            if !alive {
+                println!("{} timed out", peer.addr);
+                lost.extend(peer.drone.map(|(_, body)| body));
+            }
+            alive
+        });
+
+        for body in lost {
+            self.simulation.despawn(body);
+        }
+
+        self.respawn(dt);
+
+        // the next input of every peer, the last one is repeated when the
+        // input is late
+        for peer in self.peers.iter_mut() {
+            match peer.inputs.pop_front() {
+                Some((tick, intent)) => {
+                    peer.ack = tick;
+                    peer.intent = intent;
+                },
+                None => peer.intent.release = false,
+            }
+        }
+
+        let intents: Vec<(RigidBodyHandle, drone::Intent)> = self.peers.iter()
+            .filter_map(|peer| peer.drone.map(|(_, body)| (body, peer.intent)))
+            .collect();
+
+        self.simulation.tick(dt, &intents);
+
+        self.tick = self.tick.wrapping_add(1);
+
+        if self.tick % net::SNAPSHOT_INTERVAL == 0 {
+            let mut snapshot = self.simulation.snapshot(self.tick);
+
+            for peer in self.peers.iter() {
+                // every peer learns the last input applied to its drone and
+                // gets a delta against the last snapshot it received, or a
+                // keyframe when that one is too old
+                snapshot.ack = peer.ack;
+
+                let baseline = self.history.iter().find(|old| old.tick == peer.baseline);
+                let data = wire::encode(&snapshot, baseline);
+
+                self.link.send(&net::Message::Snapshot(data), peer.addr);
+            }
+
+            snapshot.ack = 0;
+            self.history.push_back(wire::quantise(&snapshot));
+
+            while self.history.len() > HISTORY_LENGTH {
+                self.history.pop_front();
+            }
+        }
+
+        self.link.flush();
+    }
+
+    fn handle(&mut self, from: SocketAddr, message: net::Message) {
+        let index = self.peers.iter().position(|peer| peer.addr == from);
+
+        match (message, index) {
+            (net::Message::Join, None) => {
+                let drone = self.simulation.spawn_drone(
+                    drone::Stats { is_player: true, ..Default::default() }
+                );
+
+                println!("{} joined as drone {}", from, drone.0);
+
+                let welcome = self.welcome(drone.0);
+                self.link.send(&welcome, from);
+                self.peers.push(Peer {
+                    addr: from,
+                    drone: Some(drone),
+                    respawn_in: 0.0,
+                    inputs: VecDeque::new(),
+                    intent: drone::Intent::default(),
+                    ack: 0,
+                    baseline: 0,
+                    last_seen: Instant::now(),
+                });
+            },
+            // the welcome was lost
+            (net::Message::Join, Some(i)) => {
+                let peer = &mut self.peers[i];
+                peer.last_seen = Instant::now();
+
+                if let Some((id, _)) = peer.drone {
+                    let welcome = self.welcome(id);
+                    self.link.send(&welcome, from);
+                }
+            },
+            (net::Message::Input { tick, baseline, intent }, Some(i)) => {
+                let peer = &mut self.peers[i];
+                peer.last_seen = Instant::now();
+                peer.baseline = peer.baseline.max(baseline);
+
+                let newest = peer.inputs.back().map_or(peer.ack, |(tick, _)| *tick);
+
+                // late and duplicated inputs are dropped
+                if tick > newest {
+                    peer.inputs.push_back((tick, intent));
+                }
+
+                while peer.inputs.len() > MAX_QUEUED_INPUTS {
+                    peer.inputs.pop_front();
+                }
+            },
+            (net::Message::Leave, Some(i)) => {
+                let peer = self.peers.remove(i);
+
+                println!("{} left", from);
+
+                if let Some((_, body)) = peer.drone {
+                    self.simulation.despawn(body);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Spawns new drones for the eliminated players after the delay
+    fn respawn(&mut self, dt: f32) {
+        for i in 0..self.peers.len() {
+            if let Some((_, body)) = self.peers[i].drone {
+                if !self.simulation.contains(body) {
+                    self.peers[i].drone = None;
+                    self.peers[i].respawn_in = RESPAWN_DELAY;
+                }
+                continue;
+            }
+
+            self.peers[i].respawn_in = self.peers[i].respawn_in - dt;
+
+            if self.peers[i].respawn_in <= 0.0 {
+                let drone = self.simulation.spawn_drone(
+                    drone::Stats { is_player: true, ..Default::default() }
+                );
+
+                let welcome = self.welcome(drone.0);
+                self.link.send(&welcome, self.peers[i].addr);
+                self.peers[i].drone = Some(drone);
+            }
+        }
+    }
+}
+
+// Server running on a thread of the game for a player hosting a match,
+// stopped when dropped
+pub struct Host {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Host {
+    // The simulation is built on the server thread by `build`
+    pub fn start<F>(port: u16, build: F) -> io::Result<Self>
+    where
+        F: FnOnce() -> Simulation + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+
+        let thread = {
+            let stop = stop.clone();
+
+            thread::spawn(move || {
+                let mut server = match Server::bind(("0.0.0.0", port), build()) {
+                    Ok(server) => server,
+                    Err(e)     => {
+                        let _ = sender.send(Err(e));
+                        return;
+                    },
+                };
+
+                let _ = sender.send(server.local_addr());
+
+                server.run(&stop);
+            })
+        };
+
+        let addr = receiver.recv()
+            .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "Server thread failed")))?;
+
+        Ok(Self { addr, stop, thread: Some(thread) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for Host {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        // the port is free again once the thread is done
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    const DT: f32 = 1.0 / net::TICK_RATE as f32;
+
+    fn server(bots: usize) -> Server {
+        let mut simulation = Simulation::new(&arena::Level::default(), hull::Hulls::default());
+
+        for _ in 0..bots {
+            simulation.spawn_drone(drone::Stats::default());
+        }
+
+        Server::bind("127.0.0.1:0", simulation).unwrap()
+    }
+
+    // Runs the server until every client is welcomed and got a snapshot
+    fn join(server: &mut Server, clients: &mut [net::Link]) -> Vec<(u32, net::Snapshot)> {
+        let addr = server.local_addr().unwrap();
+        let mut ids = vec![None; clients.len()];
+        let mut snapshots = vec![None; clients.len()];
+
+        for client in clients.iter_mut() {
+            client.send(&net::Message::Join, addr);
+        }
+
+        for _ in 0..500 {
+            server.update(DT);
+
+            for (i, client) in clients.iter_mut().enumerate() {
+                for (_, message) in client.receive() {
+                    match message {
+                        net::Message::Welcome { id, .. } => ids[i] = Some(id),
+                        net::Message::Snapshot(data)     => {
+                            // nothing acknowledged, so these are keyframes
+                            snapshots[i] = wire::decode(&data, |_| None);
+                        },
+                        _                                => {},
+                    }
+                }
+            }
+
+            // every snapshot has to show all the clients
+            let complete = ids.iter().all(Option::is_some) && snapshots.iter().all(|snapshot| {
+                snapshot.as_ref().map_or(false, |snapshot| ids.iter().all(|id| {
+                    snapshot.drones.iter().any(|drone| Some(drone.id) == *id)
+                }))
+            });
+
+            if complete {
+                break;
+            }
+
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        ids.into_iter()
+            .zip(snapshots.into_iter())
+            .map(|(id, snapshot)| (id.unwrap(), snapshot.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn clients_join_and_see_each_other() {
+        let mut server = server(5);
+        let mut clients: Vec<net::Link> = (0..3)
+            .map(|_| net::Link::bind("127.0.0.1:0").unwrap())
+            .collect();
+
+        let joined = join(&mut server, &mut clients);
+
+        assert_eq!(server.players(), 3);
+
+        let mut ids: Vec<u32> = joined.iter().map(|(id, _)| *id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 3);
+
+        for (_, snapshot) in joined.iter() {
+            assert_eq!(snapshot.drones.iter().filter(|d| d.is_player).count(), 3);
+            assert_eq!(snapshot.drones.len(), 8);
+        }
+    }
+
+    #[test]
+    fn input_reaches_the_peer() {
+        let mut server = server(0);
+        let mut client = net::Link::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        join(&mut server, std::slice::from_mut(&mut client));
+
+        let intent = drone::Intent { forward: true, release: true, ..Default::default() };
+
+        client.send(&net::Message::Input { tick: 2, baseline: 0, intent }, addr);
+        // older input arriving late is dropped
+        let late = drone::Intent::default();
+        client.send(&net::Message::Input { tick: 1, baseline: 0, intent: late }, addr);
+
+        for _ in 0..100 {
+            std::thread::sleep(Duration::from_millis(1));
+            server.update(DT);
+
+            if server.peers[0].ack == 2 {
+                break;
+            }
+        }
+
+        assert_eq!(server.peers[0].ack, 2);
+        assert!(server.peers[0].intent.forward);
+
+        // the input is repeated while the next one is late, without the release
+        server.update(DT);
+        assert!(server.peers[0].intent.forward);
+        assert!(!server.peers[0].intent.release);
+    }
+
+    #[test]
+    fn received_snapshots_become_baselines() {
+        let mut server = server(4);
+        let mut client = net::Link::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let (_, baseline) = join(&mut server, std::slice::from_mut(&mut client)).remove(0);
+
+        assert_eq!(baseline.beams.len(), 1);
+
+        let intent = drone::Intent::default();
+        client.send(&net::Message::Input { tick: 1, baseline: baseline.tick, intent }, addr);
+
+        let mut delta = None;
+
+        for _ in 0..100 {
+            std::thread::sleep(Duration::from_millis(1));
+            server.update(DT);
+
+            for (_, message) in client.receive() {
+                if let net::Message::Snapshot(data) = message {
+                    delta = Some(data);
+                }
+            }
+
+            if server.peers[0].baseline == baseline.tick && delta.is_some() {
+                break;
+            }
+
+            delta = None;
+        }
+
+        let delta = delta.unwrap();
+
+        // the delta needs the baseline to decode
+        assert_eq!(wire::decode(&delta, |_| None), None);
+
+        let snapshot = wire::decode(&delta, |tick| Some(&baseline).filter(|_| tick == baseline.tick));
+        assert_eq!(snapshot.unwrap().drones.len(), 5);
+    }
+
+    #[test]
+    fn intent_flies_the_drone() {
+        let level = arena::Level { beams: Vec::new(), ..Default::default() };
+        let mut simulation = Simulation::new(&level, hull::Hulls::default());
+
+        let stats = || drone::Stats { is_player: true, charge: 100.0, ..Default::default() };
+        let (_, flying) = simulation.spawn_drone(stats());
+        let (_, idle) = simulation.spawn_drone(stats());
+
+        let intent = drone::Intent { forward: true, ..Default::default() };
+
+        for _ in 0..60 {
+            simulation.tick(DT, &[(flying, intent), (idle, drone::Intent::default())]);
+        }
+
+        assert!(simulation.bodies.get(flying).unwrap().linvel().norm() > 0.1);
+        assert!(simulation.bodies.get(idle).unwrap().linvel().norm() < 1e-3);
+    }
+
+    #[test]
+    fn hosted_server_frees_the_port() {
+        let build = || Simulation::new(&arena::Level::default(), hull::Hulls::default());
+
+        let host = Host::start(0, build).unwrap();
+        let port = host.local_addr().port();
+
+        // taken while the server runs
+        assert!(Host::start(port, build).is_err());
+
+        drop(host);
+
+        assert!(Host::start(port, build).is_ok());
+    }
+
+    #[test]
+    fn leaving_removes_the_drone() {
+        let mut server = server(0);
+        let mut client = net::Link::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        join(&mut server, std::slice::from_mut(&mut client));
+
+        client.send(&net::Message::Leave, addr);
+
+        for _ in 0..100 {
+            std::thread::sleep(Duration::from_millis(1));
+            server.update(DT);
+
+            if server.players() == 0 {
+                break;
+            }
+        }
+
+        assert_eq!(server.players(), 0);
+        assert!(server.simulation.snapshot(0).drones.is_empty());
+    }
+}
@@ -1,21 +1,31 @@
-use super::{ Action, Pause, Initialization};
+use super::{ Action, Pause, flow, graphics, };
+
+use serde::{ Serialize, Deserialize, };
 
 use dotrix::ecs::{ Mut, Const };
-use dotrix::{ Window, State};
-use dotrix::math::{ Vec2i, Vec2u };
-use dotrix::services::{ Input, };
+use dotrix::{ Frame, Window, State};
+use dotrix::services::{ Input, World, };
 use dotrix::overlay::Overlay;
-use dotrix::window::{ Fullscreen, };
 
 use dotrix::egui::{
     self,
     Egui,
 };
 
+// seconds the window has to stay in place before its placement is saved
+const SAVE_DELAY: f32 = 1.0;
+
 pub struct Settings {
     pub show_info_panel: bool,
     pub god_mode: bool,
-    window_mode: WindowMode,
+    pub show_radar: bool,
+    pub radar_range: f32,
+    pub aim_assist: bool,
+    // draw the colliders, velocities and forces over the scene
+    pub debug_draw: bool,
+    pub graphics: graphics::Graphics,
+    // seconds left before the moved window is saved
+    unsaved: Option<f32>,
 }
 
 impl Default for Settings {
@@ -23,22 +33,79 @@ impl Default for Settings {
         Self {
             show_info_panel: true,
             god_mode: false,
-            window_mode: WindowMode::Windowed,
+            show_radar: true,
+            radar_range: 150.0,
+            aim_assist: true,
+            debug_draw: false,
+            graphics: graphics::Graphics::default(),
+            unsaved: None,
         }
     }
 }
 
 pub fn startup(
+    mut settings: Mut<Settings>,
+    mut window: Mut<Window>,
+    world: Const<World>,
+) {
+    // the defaults are used until the options are changed the first time
+    match graphics::Graphics::load(graphics::GRAPHICS_FILE) {
+        Ok(loaded) => settings.graphics = loaded,
+        Err(e)     => println!("{}, using the default graphics options", e),
+    }
+
+    // the monitors may have changed since the last session
+    graphics::restore(&mut settings.graphics, &graphics::areas(&window));
+
+    graphics::apply_display(&mut settings.graphics, &mut window);
+    graphics::apply_view(&settings.graphics, &world);
+}
+
+// Remembers the placement of the window for the next session
+pub fn track_window(
+    mut settings: Mut<Settings>,
     window: Const<Window>,
+    frame: Const<Frame>,
 ) {
-    window.set_outer_position(
-        Vec2i::new(
-            (window.screen_size().x - window.outer_size().x) as i32 / 2,
-            (window.screen_size().y - window.outer_size().y) as i32 / 2,
-        )
-    );
+    let size = window.inner_size();
+    let position = window.outer_position();
+
+    // a minimised window has no size
+    let windowed = settings.graphics.window_mode == WindowMode::Windowed
+        && size.x > 0 && size.y > 0;
+
+    if windowed {
+        let size = [size.x, size.y];
+        let position = [position.x, position.y];
+
+        if settings.graphics.resolution != size || settings.graphics.position != Some(position) {
+            settings.graphics.resolution = size;
+            settings.graphics.position = Some(position);
+
+            // the window may have been dragged to another monitor
+            let areas = graphics::areas(&window);
+            let centre = [position[0] + size[0] as i32 / 2, position[1] + size[1] as i32 / 2];
+
+            if let Some(monitor) = graphics::monitor_at(&areas, centre) {
+                settings.graphics.monitor = monitor;
+                settings.graphics.monitor_name = Some(areas[monitor].name.clone());
+            }
+
+            settings.unsaved = Some(SAVE_DELAY);
+        }
+    }
+
+    if let Some(delay) = settings.unsaved {
+        let delay = delay - 1.0 / frame.fps();
+
+        settings.unsaved = if delay > 0.0 { Some(delay) } else { None };
 
-    window.set_inner_size(Vec2u::new(1280, 720));
+        if delay <= 0.0 {
+            if let Err(e) = settings.graphics.save(graphics::GRAPHICS_FILE) {
+                println!("{}", e);
+            }
+        }
+    }
 }
 
 pub fn init(
@@ -53,7 +120,7 @@ pub fn ui_update (
     mut state: Mut<State>,
 ) {
     if input.is_action_activated(Action::Menu) {
-        state.push( Pause { handled: false} );
+        state.push( Pause { handled: false, confirm: Default::default(), graphics: false } );
     }
 }
 
@@ -62,6 +129,8 @@ pub fn pause_menu (
     mut settings: Mut<Settings>,
     mut window: Mut<Window>,
     mut state: Mut<State>,
+    mut flow: Mut<flow::Flow>,
+    world: Const<World>,
     input: Const<Input>,
 ) {
     window.set_cursor_grab(false);
@@ -70,7 +139,67 @@ pub fn pause_menu (
     let egui = overlay.get::<Egui>()
         .expect("Renderer does not contain an Overlay instance");
 
+    // the open dialog replaces the menu until it is answered
+    if let Some(paused) = state.get_mut::<Pause>() {
+        if paused.confirm.is_open() {
+            if input.is_action_activated(Action::Menu) {
+                paused.confirm.close();
+            }
+
+            match paused.confirm.show(&egui.ctx) {
+                Some(Confirm::Restart)  => flow.send(flow::Event::Restart),
+                Some(Confirm::NewMatch) => flow.send(flow::Event::NewMatch),
+                Some(Confirm::Exit)     => window.close(),
+                None                    => {},
+            }
+
+            return;
+        }
+
+        // the graphics page replaces the menu as well, it closes with Escape
+        if paused.graphics {
+            let mut back = input.is_action_activated(Action::Menu);
+            let mut changes = (false, false);
+
+            egui::containers::Window::new("Graphics")
+                .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::new(0.0, 0.0))
+                .collapsible(false)
+                .resizable(false)
+                .default_width(200.0)
+                .show(&egui.ctx, |ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        changes = graphics::page(ui, &mut settings.graphics, &window);
+
+                        if ui.button("Back").clicked() {
+                            back = true;
+                        }
+                    });
+                });
+
+            let (display, view) = changes;
+
+            if display {
+                graphics::apply_display(&mut settings.graphics, &mut window);
+            }
+
+            if view {
+                graphics::apply_view(&settings.graphics, &world);
+            }
+
+            if display || view {
+                if let Err(e) = settings.graphics.save(graphics::GRAPHICS_FILE) {
+                    println!("{}", e);
+                }
+            }
+
+            paused.graphics = !back;
+            return;
+        }
+    }
+
     let mut exit_pause = false;
+    let mut question = None;
+    let mut graphics_page = false;
 
     egui::containers::Window::new("Pause")
         .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::new(0.0, 0.0))
@@ -93,6 +222,41 @@ pub fn pause_menu (
                     }
                 }
 
+                if settings.show_radar == true {
+                    if ui.button("Hide radar").clicked() {
+                        settings.show_radar = false;
+                    }
+                } else {
+                    if ui.button("Show radar").clicked() {
+                        settings.show_radar = true;
+                    }
+                }
+
+                ui.add(
+                    egui::Slider::new(&mut settings.radar_range, 50.0..=500.0)
+                        .text("Radar range")
+                );
+
+                if settings.aim_assist == true {
+                    if ui.button("Aim assist: on").clicked() {
+                        settings.aim_assist = false;
+                    }
+                } else {
+                    if ui.button("Aim assist: off").clicked() {
+                        settings.aim_assist = true;
+                    }
+                }
+
+                if settings.debug_draw == true {
+                    if ui.button("Physics debug: on").clicked() {
+                        settings.debug_draw = false;
+                    }
+                } else {
+                    if ui.button("Physics debug: off").clicked() {
+                        settings.debug_draw = true;
+                    }
+                }
+
                 if settings.god_mode == true {
                     if ui.button("God mode: on").clicked() {
                         settings.god_mode = false;
@@ -103,24 +267,16 @@ pub fn pause_menu (
                     }
                 }
 
-                if settings.window_mode == WindowMode::BorderlessFullscreen {
-                    if ui.button("Windowed").clicked() {
-                        window.set_fullscreen(None);
-                        settings.window_mode = WindowMode::Windowed;
-                    }
-                } else {
-                    if ui.button("Fullscreen").clicked() {
-                        window.set_fullscreen(Some(Fullscreen::Borderless(0)));
-                        settings.window_mode = WindowMode::BorderlessFullscreen;
-                    }
+                if ui.button("Graphics").clicked() {
+                    graphics_page = true;
                 }
 
                 if ui.button("Reset the game").clicked() {
-                    state.push(Initialization {});
+                    question = Some(Confirm::Restart);
                 }
 
                 if ui.button("Exit").clicked() {
-                    window.close();
+                    question = Some(Confirm::Exit);
                 }
             }
         )
@@ -134,6 +290,24 @@ pub fn pause_menu (
             }
 
             paused.handled = true;
+            paused.graphics = graphics_page;
+
+            match question {
+                Some(Confirm::Exit) => paused.confirm.ask(
+                    "Exit",
+                    "Exit the game? The match is lost.",
+                    &[("Exit", Confirm::Exit)],
+                ),
+                Some(_) => paused.confirm.ask(
+                    "Reset the game",
+                    "The progress of the match is lost.",
+                    &[
+                        ("Restart the level", Confirm::Restart),
+                        ("Choose another level", Confirm::NewMatch),
+                    ],
+                ),
+                None => {},
+            }
         }
     }
 
@@ -144,8 +318,46 @@ pub fn pause_menu (
     }
 }
 
+// Pause menu actions that wait for a confirmation
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Confirm {
+    // the same level again
+    Restart,
+    // back to the lobby
+    NewMatch,
+    Exit,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
 pub enum WindowMode {
     BorderlessFullscreen,
+    ExclusiveFullscreen,
     Windowed,
 }
+
+impl WindowMode {
+    pub fn next(self) -> Self {
+        match self {
+            WindowMode::Windowed             => WindowMode::BorderlessFullscreen,
+            WindowMode::BorderlessFullscreen => WindowMode::ExclusiveFullscreen,
+            WindowMode::ExclusiveFullscreen  => WindowMode::Windowed,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum GameMode {
+    Classic,
+    Survival,
+    KingOfTheBeam,
+}
+
+impl GameMode {
+    pub fn next(self) -> Self {
+        match self {
+            GameMode::Classic       => GameMode::Survival,
+            GameMode::Survival      => GameMode::KingOfTheBeam,
+            GameMode::KingOfTheBeam => GameMode::Classic,
+        }
+    }
+}
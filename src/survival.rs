@@ -0,0 +1,267 @@
+use super::{ drone, hull, lobby, settings::GameMode, };
+
+use serde::Deserialize;
+use rand::Rng;
+
+use rapier3d::{
+    dynamics::{ RigidBodySet, },
+    geometry::{ ColliderSet, },
+};
+
+use dotrix::{
+    Frame,
+    services::{ Assets, World, },
+    math::{ Point3, },
+    ecs::{ Mut, Const, },
+};
+
+const CONFIG: &str = "assets/modes/survival.ron";
+
+// Box the bots of a wave appear in
+#[derive(Debug, Clone, Deserialize)]
+pub struct Volume {
+    pub min: (f32, f32, f32),
+    pub max: (f32, f32, f32),
+}
+
+impl Volume {
+    // Swaps the bounds given in the wrong order, the random range needs
+    // min <= max on every axis
+    fn normalise(&mut self) {
+        let (min, max) = (self.min, self.max);
+
+        self.min = (min.0.min(max.0), min.1.min(max.1), min.2.min(max.2));
+        self.max = (min.0.max(max.0), min.1.max(max.1), min.2.max(max.2));
+    }
+
+    fn random_point(&self, rng: &mut impl Rng) -> Point3 {
+        Point3::new(
+            rng.gen_range(self.min.0..=self.max.0),
+            rng.gen_range(self.min.1..=self.max.1),
+            rng.gen_range(self.min.2..=self.max.2),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DroneSpec {
+    pub health: f32,
+    pub charge: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Wave {
+    // seconds between the previous wave elimination and this one
+    pub delay: f32,
+    pub bots: usize,
+    pub aggression: f32,
+    pub spec: DroneSpec,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub spawn_volumes: Vec<Volume>,
+    // bot count and aggression multiplier of waves after the last one
+    pub growth: f32,
+    pub waves: Vec<Wave>,
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path, e))?;
+
+        let mut config: Self = ron::from_str(&data)
+            .map_err(|e| format!("Could not parse {}: {}", path, e))?;
+
+        for volume in config.spawn_volumes.iter_mut() {
+            volume.normalise();
+        }
+
+        Ok(config)
+    }
+
+    // Wave by its number starting from 1, the waves after the last one
+    // defined are escalated by `growth`
+    pub fn wave(&self, number: usize) -> Option<Wave> {
+        let last = self.waves.len();
+        if number == 0 || last == 0 {
+            return None;
+        }
+
+        if number <= last {
+            return Some(self.waves[number - 1].clone());
+        }
+
+        let factor = self.growth.powi((number - last) as i32);
+        let mut wave = self.waves[last - 1].clone();
+        wave.bots = (wave.bots as f32 * factor).round() as usize;
+        wave.aggression = wave.aggression * factor;
+
+        Some(wave)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            spawn_volumes: vec![
+                Volume { min: (-100.0, -50.0, -100.0), max: (100.0, 50.0, 100.0) },
+            ],
+            growth: 1.25,
+            waves: vec![
+                Wave {
+                    delay: 3.0,
+                    bots: 3,
+                    aggression: 0.3,
+                    spec: DroneSpec { health: 100.0, charge: 25.0 },
+                },
+            ],
+        }
+    }
+}
+
+pub struct Survival {
+    pub config: Config,
+    // number of the current wave, 0 before the first one
+    pub wave: usize,
+    // seconds left before the next wave
+    pub countdown: f32,
+    // multiplier of the wave aggression set by the difficulty
+    pub aggression: f32,
+}
+
+impl Default for Survival {
+    fn default() -> Self {
+        let config = Config::load(CONFIG).unwrap_or_else(|e| {
+            println!("{}, using default waves", e);
+            Config::default()
+        });
+
+        Self {
+            config,
+            wave: 0,
+            countdown: 0.0,
+            aggression: 1.0,
+        }
+    }
+}
+
+impl Survival {
+    pub fn reset(&mut self, aggression: f32) {
+        self.wave = 0;
+        self.aggression = aggression;
+        self.countdown = self.config.wave(1).map_or(0.0, |wave| wave.delay);
+    }
+}
+
+pub fn update(
+    mut world: Mut<World>,
+    mut assets: Mut<Assets>,
+    mut bodies: Mut<RigidBodySet>,
+    mut colliders: Mut<ColliderSet>,
+    hulls: Const<hull::Hulls>,
+    active: Const<lobby::Active>,
+    frame: Const<Frame>,
+    mut survival: Mut<Survival>,
+) {
+    if active.mode != GameMode::Survival {
+        return;
+    }
+
+    let bots = world.query::<( &drone::Stats, )>()
+        .filter(|(stats, )| !stats.is_player)
+        .count();
+
+    if bots > 0 {
+        return;
+    }
+
+    survival.countdown = survival.countdown - 1.0 / frame.fps();
+
+    if survival.countdown > 0.0 {
+        return;
+    }
+
+    survival.wave = survival.wave + 1;
+
+    let wave = match survival.config.wave(survival.wave) {
+        Some(wave) => wave,
+        None       => return,
+    };
+
+    let mut rng = rand::thread_rng();
+    let volumes = &survival.config.spawn_volumes;
+
+    if volumes.is_empty() {
+        return;
+    }
+
+    for i in 0..wave.bots {
+        let volume = &volumes[i % volumes.len()];
+
+        drone::spawn(
+            &mut world,
+            &mut assets,
+            &mut bodies,
+            &mut colliders,
+            &hulls,
+            volume.random_point(&mut rng),
+            drone::Stats {
+                health: wave.spec.health,
+                charge: wave.spec.charge,
+                aggression: wave.aggression * survival.aggression,
+                ..Default::default()
+            },
+        );
+    }
+
+    survival.countdown = survival.config.wave(survival.wave + 1)
+        .map_or(0.0, |wave| wave.delay);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_config_parses() {
+        let config = Config::load(CONFIG).unwrap();
+
+        assert!(!config.waves.is_empty());
+        assert!(!config.spawn_volumes.is_empty());
+    }
+
+    #[test]
+    fn reversed_volumes_are_normalised() {
+        let mut volume = Volume { min: (10.0, -5.0, 3.0), max: (-10.0, 5.0, -3.0) };
+        volume.normalise();
+
+        assert_eq!(volume.min, (-10.0, -5.0, -3.0));
+        assert_eq!(volume.max, (10.0, 5.0, 3.0));
+
+        // picking a point no longer panics
+        let point = volume.random_point(&mut rand::thread_rng());
+        assert!(point.x >= -10.0 && point.x <= 10.0);
+    }
+
+    #[test]
+    fn waves_grow_after_the_last_one() {
+        let config = Config {
+            growth: 2.0,
+            ..Default::default()
+        };
+        let last = config.waves[0].clone();
+
+        assert!(config.wave(0).is_none());
+        assert_eq!(config.wave(1).unwrap().bots, last.bots);
+
+        let second = config.wave(2).unwrap();
+        assert_eq!(second.bots, last.bots * 2);
+        assert_eq!(second.aggression, last.aggression * 2.0);
+
+        let third = config.wave(3).unwrap();
+        assert_eq!(third.bots, last.bots * 4);
+        assert_eq!(third.spec.health, last.spec.health);
+    }
+}
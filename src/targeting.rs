@@ -0,0 +1,334 @@
+use super::{ Action, drone, local, settings, view };
+
+use rapier3d::{
+    dynamics::{ RigidBodySet, RigidBodyHandle, },
+    na::{ Vector3, },
+};
+
+use dotrix::ecs::{ Mut, Const, Entity, };
+use dotrix::services::{ World, Camera, Input, };
+use dotrix::overlay::Overlay;
+
+use dotrix::egui::{
+    self,
+    Egui,
+};
+
+// half angle of the view cone to search the target in
+const VIEW_CONE: f32 = 0.35;
+const LOCK_RANGE: f32 = 250.0;
+// portion of the strike direction turned towards the intercept point
+pub const AIM_ASSIST: f32 = 0.75;
+
+// Target locked by a player
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Lock {
+    pub target: Option<Entity>,
+    // predicted point to strike at to hit the target
+    pub intercept: Option<Vector3<f32>>,
+}
+
+// Locks of the local players by their slot
+pub struct LockOn {
+    pub slots: [Lock; local::MAX_PLAYERS],
+}
+
+impl Default for LockOn {
+    fn default() -> Self {
+        Self {
+            slots: [Lock::default(); local::MAX_PLAYERS],
+        }
+    }
+}
+
+// Point where a projectile launched from `origin` with `speed` meets the
+// target moving with constant `velocity`
+pub fn intercept(
+    origin: Vector3<f32>,
+    target: Vector3<f32>,
+    velocity: Vector3<f32>,
+    speed: f32,
+) -> Option<Vector3<f32>> {
+    // nothing is launched without a strike charge
+    if speed <= 0.0 {
+        return None;
+    }
+
+    let offset = target - origin;
+
+    // solve |offset + velocity * t| = speed * t
+    let a = velocity.dot(&velocity) - speed * speed;
+    let b = 2.0 * offset.dot(&velocity);
+    let c = offset.dot(&offset);
+
+    let time = if a.abs() < f32::EPSILON {
+        if b.abs() < f32::EPSILON {
+            return None;
+        }
+        -c / b
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrt = discriminant.sqrt();
+        let t1 = (-b - sqrt) / (2.0 * a);
+        let t2 = (-b + sqrt) / (2.0 * a);
+
+        match (t1 > 0.0, t2 > 0.0) {
+            (true, true)  => t1.min(t2),
+            (true, false) => t1,
+            (false, true) => t2,
+            _             => return None,
+        }
+    };
+
+    if time > 0.0 {
+        Some(target + velocity * time)
+    } else {
+        None
+    }
+}
+
+pub fn lock(
+    world: Const<World>,
+    bodies: Const<RigidBodySet>,
+    input: Const<Input>,
+    mut lock_on: Mut<LockOn>,
+) {
+    // find the local players
+    let mut players = [None; local::MAX_PLAYERS];
+
+    let query = world.query::<( &Entity, &mut RigidBodyHandle, &mut drone::Stats )>();
+
+    for (entity, rigid_body, stats) in query {
+        if stats.is_player & (stats.slot < local::MAX_PLAYERS) {
+            players[stats.slot] = Some((*entity, *rigid_body, stats.strike_charge));
+        }
+    }
+
+    for (slot, player) in players.iter().enumerate() {
+        let toggled = input.is_action_activated(
+            local::action(slot, Action::LockOn, local::Control::LockOn)
+        );
+
+        update_lock(&mut lock_on.slots[slot], *player, toggled, &world, &bodies);
+    }
+}
+
+// Toggles the lock of a player and updates the intercept point of its target
+fn update_lock(
+    lock: &mut Lock,
+    player: Option<(Entity, RigidBodyHandle, f32)>,
+    toggled: bool,
+    world: &World,
+    bodies: &RigidBodySet,
+) {
+    let (player_entity, player_body, strike_charge) = match player {
+        Some(player) => player,
+        None         => {
+            lock.target = None;
+            lock.intercept = None;
+            return;
+        }
+    };
+
+    let body = bodies.get(player_body).unwrap();
+    let position = body.position().translation.vector;
+    let (fwd, _) = drone::axes(&drone::rotation(body));
+
+    if toggled {
+        if lock.target.is_some() {
+            lock.target = None;
+        } else {
+            // select the nearest drone inside the view cone
+            let mut nearest = None;
+            let mut nearest_distance = LOCK_RANGE;
+
+            let query = world.query::<( &Entity, &mut RigidBodyHandle, &mut drone::Stats )>();
+
+            for (entity, rigid_body, _) in query {
+                if *entity == player_entity {
+                    continue;
+                }
+
+                let offset = bodies.get(*rigid_body).unwrap()
+                    .position().translation.vector - position;
+                let distance = offset.norm();
+
+                if distance < nearest_distance
+                    && offset.normalize().dot(&fwd) > VIEW_CONE.cos()
+                {
+                    nearest = Some(*entity);
+                    nearest_distance = distance;
+                }
+            }
+
+            lock.target = nearest;
+        }
+    }
+
+    // update the intercept point, release the target if it is gone
+    let mut target = None;
+
+    if let Some(target_entity) = lock.target {
+        let query = world.query::<( &Entity, &mut RigidBodyHandle, &mut drone::Stats )>();
+
+        for (entity, rigid_body, _) in query {
+            if *entity == target_entity {
+                target = bodies.get(*rigid_body);
+            }
+        }
+    }
+
+    match target {
+        Some(target) => {
+            let target_position = target.position().translation.vector;
+            let velocity = target.linvel() - body.linvel();
+            let speed = strike_charge * drone::STRIKE_IMPULSE / body.mass();
+
+            lock.intercept = Some(
+                intercept(position, target_position, velocity, speed)
+                    .unwrap_or(target_position)
+            );
+        },
+        None => {
+            lock.target = None;
+            lock.intercept = None;
+        }
+    }
+}
+
+// Strike direction biased towards the locked target
+pub fn aim(
+    position: Vector3<f32>,
+    fwd: Vector3<f32>,
+    intercept: Option<Vector3<f32>>,
+) -> Vector3<f32> {
+    match intercept {
+        Some(point) if point != position => {
+            let to_target = (point - position).normalize();
+            (fwd * (1.0 - AIM_ASSIST) + to_target * AIM_ASSIST).normalize()
+        },
+        _ => fwd,
+    }
+}
+
+pub fn reticle(
+    world: Const<World>,
+    overlay: Const<Overlay>,
+    bodies: Const<RigidBodySet>,
+    camera: Const<Camera>,
+    lock_on: Const<LockOn>,
+    settings: Const<settings::Settings>,
+) {
+    if lock_on.slots.iter().all(|lock| lock.target.is_none()) {
+        return;
+    }
+
+    let egui = overlay.get::<Egui>()
+        .expect("Renderer does not contain an Overlay instance");
+
+    let screen = egui.ctx.input().screen_rect();
+    let painter = egui.ctx.layer_painter(
+        egui::LayerId::new(egui::Order::Background, egui::Id::new("reticle"))
+    );
+
+    let color = egui::Color32::from_rgb(255, 64, 64);
+    let stroke = egui::Stroke::new(1.5, color);
+
+    let query = world.query::<( &Entity, &mut RigidBodyHandle, &mut drone::Stats )>();
+
+    for (entity, rigid_body, _) in query {
+        if !lock_on.slots.iter().any(|lock| lock.target == Some(*entity)) {
+            continue;
+        }
+
+        let position = bodies.get(*rigid_body).unwrap().position().translation.vector;
+
+        if let Some(center) = view::to_screen(&camera, position, screen) {
+            let size = 14.0;
+            painter.circle_stroke(center, size, stroke);
+
+            for direction in &[
+                egui::Vec2::new(1.0, 0.0),
+                egui::Vec2::new(-1.0, 0.0),
+                egui::Vec2::new(0.0, 1.0),
+                egui::Vec2::new(0.0, -1.0),
+            ] {
+                painter.line_segment(
+                    [center + *direction * size * 0.6, center + *direction * size * 1.4],
+                    stroke,
+                );
+            }
+        }
+    }
+
+    if settings.aim_assist {
+        for point in lock_on.slots.iter().filter_map(|lock| lock.intercept) {
+            if let Some(center) = view::to_screen(&camera, point, screen) {
+                painter.circle_filled(center, 3.0, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stationary_targets_are_hit_where_they_are() {
+        let target = Vector3::new(10.0, 0.0, 0.0);
+
+        let point = intercept(Vector3::zeros(), target, Vector3::zeros(), 5.0).unwrap();
+
+        assert!((point - target).norm() < 1e-4);
+    }
+
+    #[test]
+    fn moving_targets_are_led() {
+        let target = Vector3::new(10.0, 0.0, 0.0);
+        let velocity = Vector3::new(0.0, 0.0, 3.0);
+        let speed = 5.0;
+
+        let point = intercept(Vector3::zeros(), target, velocity, speed).unwrap();
+
+        // the strike and the target arrive at the same time, 2.5 seconds
+        let time = point.norm() / speed;
+        assert!((target + velocity * time - point).norm() < 1e-3);
+        assert!((time - 2.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn faster_targets_moving_away_are_out_of_reach() {
+        let target = Vector3::new(10.0, 0.0, 0.0);
+        let velocity = Vector3::new(8.0, 0.0, 0.0);
+
+        assert_eq!(intercept(Vector3::zeros(), target, velocity, 5.0), None);
+    }
+
+    #[test]
+    fn zero_strike_charge_has_no_intercept() {
+        let target = Vector3::new(10.0, 0.0, 0.0);
+
+        assert_eq!(intercept(Vector3::zeros(), target, Vector3::zeros(), 0.0), None);
+        // not even for a target flying into the drone
+        assert_eq!(intercept(Vector3::zeros(), target, Vector3::new(-5.0, 0.0, 0.0), 0.0), None);
+    }
+
+    #[test]
+    fn aim_leans_towards_the_intercept() {
+        let fwd = Vector3::new(1.0, 0.0, 0.0);
+        let point = Vector3::new(0.0, 0.0, 10.0);
+
+        let direction = aim(Vector3::zeros(), fwd, Some(point));
+
+        assert!((direction.norm() - 1.0).abs() < 1e-5);
+        assert!(direction.z > 0.0 && direction.x > 0.0);
+        // nothing to lean towards
+        assert_eq!(aim(Vector3::zeros(), fwd, None), fwd);
+        assert_eq!(aim(Vector3::zeros(), fwd, Some(Vector3::zeros())), fwd);
+    }
+}
@@ -0,0 +1,393 @@
+use super::{ Action, drone, beam };
+
+use rapier3d::{
+    dynamics::{ RigidBodySet, RigidBodyHandle, },
+    geometry::{ Collider, ColliderHandle, ColliderSet, InteractionGroups, Ray, },
+    pipeline::QueryPipeline,
+    na::{ self, Vector3, },
+};
+
+use dotrix::{
+    Frame,
+    services::{ Camera, Input, World, },
+    math::{ Point3, },
+    ecs::{ Mut, Const, Entity, },
+    egui,
+};
+
+// vertical field of view of the renderer projection
+pub const FOV: f32 = 1.1;
+const NEAR: f32 = 0.0625;
+
+// direction the camera is looking at
+pub fn forward(camera: &Camera) -> Vector3<f32> {
+    -Vector3::new(
+        camera.xz_angle.cos() * camera.y_angle.cos(),
+        camera.xz_angle.sin(),
+        camera.xz_angle.cos() * camera.y_angle.sin(),
+    )
+}
+
+// camera position in the world space
+pub fn eye(camera: &Camera) -> Vector3<f32> {
+    let target = Vector3::new(camera.target.x, camera.target.y, camera.target.z);
+
+    target - forward(camera) * camera.distance
+}
+
+// Projects a point from the world space onto the screen, returns None for
+// points behind the camera
+pub fn to_screen(
+    camera: &Camera,
+    point: Vector3<f32>,
+    screen: egui::Rect,
+) -> Option<egui::Pos2> {
+    let fwd = forward(camera);
+    let right = fwd.cross(&Vector3::y()).normalize();
+    let up = right.cross(&fwd);
+
+    let offset = point - eye(camera);
+    let depth = offset.dot(&fwd);
+
+    if depth <= NEAR {
+        return None;
+    }
+
+    let half_height = depth * (FOV / 2.0).tan();
+    let half_width = half_height * screen.width() / screen.height();
+
+    let x = offset.dot(&right) / half_width;
+    let y = offset.dot(&up) / half_height;
+
+    Some(screen.center() + egui::Vec2::new(
+        x * screen.width() / 2.0,
+        -y * screen.height() / 2.0,
+    ))
+}
+
+// Size on the screen of a sphere with `radius` at `point`
+pub fn screen_radius(
+    camera: &Camera,
+    point: Vector3<f32>,
+    radius: f32,
+    screen: egui::Rect,
+) -> Option<f32> {
+    let depth = (point - eye(camera)).dot(&forward(camera));
+
+    if depth <= NEAR {
+        return None;
+    }
+
+    Some(radius / (depth * (FOV / 2.0).tan()) * screen.height() / 2.0)
+}
+
+// free-fly camera speed, units per second
+const FREE_FLY_SPEED: f32 = 20.0;
+// orbiting speed around a beam, radians per second
+const ORBIT_SPEED: f32 = 0.1;
+
+// chase camera spring stiffness, the damping keeps it critical
+const STIFFNESS: f32 = 40.0;
+// how far ahead (in seconds of flight) the camera looks
+const LOOK_AHEAD: f32 = 0.25;
+const ZOOM_SPEED: f32 = 1.0;
+const MIN_DISTANCE: f32 = 2.0;
+const MAX_DISTANCE: f32 = 50.0;
+// gap kept between the camera and an occluding body
+const OCCLUSION_MARGIN: f32 = 0.5;
+// rate the camera moves back out after occlusion, units per second
+const RELEASE_SPEED: f32 = 10.0;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum CameraMode {
+    FollowPlayer,
+    FollowDrone,
+    OrbitBeam,
+    FreeFly,
+}
+
+impl CameraMode {
+    fn next(self) -> Self {
+        match self {
+            CameraMode::FollowPlayer => CameraMode::FollowDrone,
+            CameraMode::FollowDrone  => CameraMode::OrbitBeam,
+            CameraMode::OrbitBeam    => CameraMode::FreeFly,
+            CameraMode::FreeFly      => CameraMode::FollowPlayer,
+        }
+    }
+}
+
+pub struct Rig {
+    pub mode: CameraMode,
+    // drone or beam the camera is following
+    pub followed: Option<Entity>,
+    // distance to the target chosen by the mouse wheel
+    pub zoom: f32,
+    // smoothed camera target and its velocity
+    position: Vector3<f32>,
+    velocity: Vector3<f32>,
+    // jump to the target instead of chasing it on the next frame
+    snap: bool,
+}
+
+impl Default for Rig {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::FollowPlayer,
+            followed: None,
+            zoom: 10.0,
+            position: Vector3::new(0.0, 0.0, 0.0),
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            snap: true,
+        }
+    }
+}
+
+impl Rig {
+    // true if the player drone should be controlled by the input
+    pub fn controls_player(&self) -> bool {
+        self.mode == CameraMode::FollowPlayer
+    }
+
+    pub fn reset(&mut self, distance: f32) {
+        self.mode = CameraMode::FollowPlayer;
+        self.followed = None;
+        self.zoom = distance;
+        self.snap = true;
+    }
+
+    // Moves the smoothed target with a critically damped spring
+    fn chase(&mut self, goal: Vector3<f32>, dt: f32) {
+        if self.snap {
+            self.position = goal;
+            self.velocity = Vector3::new(0.0, 0.0, 0.0);
+            self.snap = false;
+            return;
+        }
+
+        let damping = 2.0 * STIFFNESS.sqrt();
+        let acceleration = (goal - self.position) * STIFFNESS - self.velocity * damping;
+
+        self.velocity = self.velocity + acceleration * dt;
+        self.position = self.position + self.velocity * dt;
+    }
+}
+
+// Object the camera can follow
+#[derive(Copy, Clone)]
+struct Followed {
+    entity: Entity,
+    body: RigidBodyHandle,
+    position: Vector3<f32>,
+    velocity: Vector3<f32>,
+}
+
+// Picks the followed object from the list, or the one next to it
+fn cycle(
+    list: &[Followed],
+    current: Option<Entity>,
+    next: bool,
+) -> Option<Followed> {
+    let index = current.and_then(|entity|
+        list.iter().position(|f| f.entity == entity)
+    );
+
+    match index {
+        Some(i) if !next => Some(list[i]),
+        Some(i)          => Some(list[(i + 1) % list.len()]),
+        None             => list.first().copied(),
+    }
+}
+
+// The primary player moved to the center of all local players, and the
+// distance from the center to the farthest of them
+fn frame_players(players: &[(usize, Followed)]) -> (Option<Followed>, f32) {
+    let primary = players.iter()
+        .find(|(slot, _)| *slot == 0)
+        .or(players.first())
+        .map(|(_, followed)| *followed);
+
+    let mut primary = match primary {
+        Some(primary) => primary,
+        None          => return (None, 0.0),
+    };
+
+    let count = players.len() as f32;
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+
+    primary.position = players.iter().fold(zero, |sum, (_, f)| sum + f.position) / count;
+    primary.velocity = players.iter().fold(zero, |sum, (_, f)| sum + f.velocity) / count;
+
+    let spread = players.iter()
+        .map(|(_, f)| (f.position - primary.position).norm())
+        .fold(0.0, f32::max);
+
+    (Some(primary), spread)
+}
+
+pub fn control(
+    world: Const<World>,
+    bodies: Const<RigidBodySet>,
+    colliders: Const<ColliderSet>,
+    query_pipeline: Const<QueryPipeline>,
+    input: Const<Input>,
+    frame: Const<Frame>,
+    mut camera: Mut<Camera>,
+    mut rig: Mut<Rig>,
+) {
+    let dt = 1.0 / frame.fps();
+
+    let mut players = Vec::new();
+    let mut bots = Vec::new();
+    let mut beams = Vec::new();
+
+    let query = world.query::<( &Entity, &mut RigidBodyHandle, &mut drone::Stats )>();
+
+    for (entity, rigid_body, stats) in query {
+        let body = bodies.get(*rigid_body).unwrap();
+        let followed = Followed {
+            entity: *entity,
+            body: *rigid_body,
+            position: body.position().translation.vector,
+            velocity: *body.linvel(),
+        };
+
+        if stats.is_player {
+            players.push((stats.slot, followed));
+        } else {
+            bots.push(followed);
+        }
+    }
+
+    let query = world.query::<( &Entity, &mut RigidBodyHandle, &mut beam::Stats )>();
+
+    for (entity, rigid_body, _) in query {
+        let body = bodies.get(*rigid_body).unwrap();
+        beams.push(Followed {
+            entity: *entity,
+            body: *rigid_body,
+            position: body.position().translation.vector,
+            velocity: *body.linvel(),
+        });
+    }
+
+    // the camera is shared, with several local players it follows their
+    // center keeping all of them in the view
+    let (player, spread) = frame_players(&players);
+
+    if input.is_action_activated(Action::CameraMode) {
+        rig.mode = rig.mode.next();
+        rig.followed = None;
+    }
+
+    // spectate bots after the player is eliminated
+    if rig.mode == CameraMode::FollowPlayer && player.is_none() {
+        rig.mode = CameraMode::FollowDrone;
+    }
+
+    if rig.mode == CameraMode::FreeFly {
+        let fwd = forward(&camera);
+        let side = Vector3::y().cross(&fwd).normalize();
+
+        let mut dir = Vector3::new(0.0, 0.0, 0.0);
+
+        if input.is_action_hold(Action::MoveForward) {
+            dir = dir + fwd;
+        };
+        if input.is_action_hold(Action::MoveBackward) {
+            dir = dir - fwd;
+        };
+        if input.is_action_hold(Action::MoveLeft) {
+            dir = dir + side;
+        };
+        if input.is_action_hold(Action::MoveRight) {
+            dir = dir - side;
+        };
+        if input.is_action_hold(Action::MoveUp) {
+            dir = dir + Vector3::y();
+        };
+        if input.is_action_hold(Action::MoveDown) {
+            dir = dir - Vector3::y();
+        };
+
+        let spd = if input.is_action_hold(Action::Accelerate) {
+            FREE_FLY_SPEED * 5.0
+        } else {
+            FREE_FLY_SPEED
+        };
+
+        let mut target = Vector3::new(camera.target.x, camera.target.y, camera.target.z);
+
+        if dir != Vector3::new(0.0, 0.0, 0.0) {
+            target = target + dir.normalize() * spd * dt;
+        }
+
+        // look from the target itself, chasing restarts from here
+        camera.target = Point3::new(target.x, target.y, target.z);
+        camera.distance = 0.0;
+        rig.position = target;
+        rig.velocity = Vector3::new(0.0, 0.0, 0.0);
+
+        return;
+    }
+
+    let next = input.is_action_activated(Action::NextTarget);
+
+    let followed = match rig.mode {
+        CameraMode::FollowPlayer => player,
+        CameraMode::FollowDrone  => cycle(&bots, rig.followed, next),
+        CameraMode::OrbitBeam    => {
+            camera.y_angle = camera.y_angle + ORBIT_SPEED * dt;
+            cycle(&beams, rig.followed, next)
+        },
+        CameraMode::FreeFly      => None,
+    };
+
+    let followed = match followed {
+        Some(followed) => followed,
+        None           => return,
+    };
+
+    if rig.followed != Some(followed.entity) {
+        rig.followed = Some(followed.entity);
+        rig.snap = true;
+    }
+
+    rig.zoom = (rig.zoom - ZOOM_SPEED * input.mouse_scroll())
+        .max(MIN_DISTANCE)
+        .min(MAX_DISTANCE);
+
+    let zoom = if rig.mode == CameraMode::FollowPlayer {
+        rig.zoom.max(spread / (FOV / 2.0).tan())
+    } else {
+        rig.zoom
+    };
+
+    rig.chase(followed.position + followed.velocity * LOOK_AHEAD, dt);
+
+    camera.target = Point3::new(rig.position.x, rig.position.y, rig.position.z);
+
+    // pull the camera in when something is between it and the target
+    let ray = Ray::new(na::Point3::from(rig.position), -forward(&camera));
+    let filter = |_: ColliderHandle, collider: &Collider| {
+        collider.parent() != followed.body && !collider.is_sensor()
+    };
+
+    let distance = match query_pipeline.cast_ray(
+        &colliders,
+        &ray,
+        zoom,
+        true,
+        InteractionGroups::all(),
+        Some(&filter),
+    ) {
+        Some((_, toi)) => (toi - OCCLUSION_MARGIN).max(0.0),
+        None           => zoom,
+    };
+
+    camera.distance = if distance < camera.distance {
+        distance
+    } else {
+        (camera.distance + RELEASE_SPEED * dt).min(distance)
+    };
+}
@@ -0,0 +1,595 @@
+use super::{ beam, net::{ BeamState, DroneState, Snapshot, }, };
+
+use std::collections::HashMap;
+use std::f32::consts::FRAC_1_SQRT_2;
+
+// Binary snapshot format of the network game and the replays. A snapshot is
+// either a keyframe or a delta against a baseline snapshot the receiver
+// already has, i.e. the last one it acknowledged.
+//
+//     version   u8
+//     tick      varint
+//     ack       varint
+//     baseline  varint, ticks before `tick`, 0 for a keyframe
+//     drones    varint count, then per drone the id difference to the
+//               previous drone, a flags byte and the changed field groups
+//     beams     the same as the drones
+//
+// Fields are quantised to integers and written as zigzag varints of the
+// difference to the baseline, entities missing in the baseline are diffed
+// against zeros. Entities are sorted by id.
+
+// bump on any change of the layout, other versions are rejected
+pub const VERSION: u8 = 1;
+
+// steps per unit of the positions and velocities
+const POSITION_SCALE: f32 = 256.0;
+// steps per unit of the stats
+const STAT_SCALE: f32 = 1024.0;
+// steps of the three smallest quaternion components
+const ROTATION_SCALE: f32 = 32767.0;
+
+// drone field groups: position, velocity, spin, rotation and stats
+const DRONE_GROUPS: [(usize, usize); 5] = [(0, 3), (3, 6), (6, 9), (9, 13), (13, 18)];
+const DRONE_FIELDS: usize = 18;
+// flag of the player drones next to the group bits
+const PLAYER: u8 = 1 << 7;
+
+// beam field groups: position and stats
+const BEAM_GROUPS: [(usize, usize); 2] = [(0, 3), (3, 9)];
+const BEAM_FIELDS: usize = 9;
+
+pub fn encode(snapshot: &Snapshot, baseline: Option<&Snapshot>) -> Vec<u8> {
+    // a baseline of the same tick can't be told from a keyframe
+    let baseline = baseline.filter(|baseline| baseline.tick != snapshot.tick);
+
+    let mut writer = Writer::default();
+
+    writer.byte(VERSION);
+    writer.varint(snapshot.tick);
+    writer.varint(snapshot.ack);
+    writer.varint(baseline.map_or(0, |baseline| snapshot.tick.wrapping_sub(baseline.tick)));
+
+    let base: HashMap<u32, [i32; DRONE_FIELDS]> = baseline
+        .map(|baseline| baseline.drones.iter().map(|old| (old.id, drone_fields(old))).collect())
+        .unwrap_or_default();
+
+    let mut drones: Vec<&DroneState> = snapshot.drones.iter().collect();
+    drones.sort_by_key(|drone| drone.id);
+
+    writer.varint(drones.len() as u32);
+
+    let mut previous = 0;
+
+    for drone in drones {
+        writer.varint(drone.id.wrapping_sub(previous));
+        previous = drone.id;
+
+        let flags = if drone.is_player { PLAYER } else { 0 };
+        let zeros = [0; DRONE_FIELDS];
+        let base = base.get(&drone.id).unwrap_or(&zeros);
+
+        writer.fields(&drone_fields(drone), base, &DRONE_GROUPS, flags);
+    }
+
+    let base: HashMap<u32, [i32; BEAM_FIELDS]> = baseline
+        .map(|baseline| baseline.beams.iter().map(|old| (old.id, beam_fields(old))).collect())
+        .unwrap_or_default();
+
+    let mut beams: Vec<&BeamState> = snapshot.beams.iter().collect();
+    beams.sort_by_key(|beam| beam.id);
+
+    writer.varint(beams.len() as u32);
+
+    let mut previous = 0;
+
+    for beam in beams {
+        writer.varint(beam.id.wrapping_sub(previous));
+        previous = beam.id;
+
+        let zeros = [0; BEAM_FIELDS];
+        let base = base.get(&beam.id).unwrap_or(&zeros);
+
+        writer.fields(&beam_fields(beam), base, &BEAM_GROUPS, 0);
+    }
+
+    writer.bytes
+}
+
+// Decodes the snapshot, `baseline` finds the received snapshot of a tick.
+// None for malformed data, other versions and missing baselines.
+pub fn decode<'a, F>(data: &[u8], baseline: F) -> Option<Snapshot>
+where
+    F: FnOnce(u32) -> Option<&'a Snapshot>,
+{
+    let mut reader = Reader { data, position: 0 };
+
+    if reader.byte()? != VERSION {
+        return None;
+    }
+
+    let tick = reader.varint()?;
+    let ack = reader.varint()?;
+    let distance = reader.varint()?;
+
+    let baseline = match distance {
+        0 => None,
+        _ => Some(baseline(tick.wrapping_sub(distance))?),
+    };
+
+    let base: HashMap<u32, [i32; DRONE_FIELDS]> = baseline
+        .map(|baseline| baseline.drones.iter().map(|old| (old.id, drone_fields(old))).collect())
+        .unwrap_or_default();
+
+    let mut drones = Vec::new();
+    let mut id = 0u32;
+
+    for _ in 0..reader.varint()? {
+        id = id.wrapping_add(reader.varint()?);
+
+        let zeros = [0; DRONE_FIELDS];
+        let (flags, fields) = reader.fields(base.get(&id).unwrap_or(&zeros), &DRONE_GROUPS)?;
+
+        drones.push(drone_state(id, flags & PLAYER != 0, &fields));
+    }
+
+    let base: HashMap<u32, [i32; BEAM_FIELDS]> = baseline
+        .map(|baseline| baseline.beams.iter().map(|old| (old.id, beam_fields(old))).collect())
+        .unwrap_or_default();
+
+    let mut beams = Vec::new();
+    let mut id = 0u32;
+
+    for _ in 0..reader.varint()? {
+        id = id.wrapping_add(reader.varint()?);
+
+        let zeros = [0; BEAM_FIELDS];
+        let (_, fields) = reader.fields(base.get(&id).unwrap_or(&zeros), &BEAM_GROUPS)?;
+
+        beams.push(beam_state(id, &fields)?);
+    }
+
+    // trailing bytes mean the packet is not what it claims to be
+    if reader.position != data.len() {
+        return None;
+    }
+
+    Some(Snapshot { tick, ack, drones, beams })
+}
+
+// The snapshot as the receiver decodes it, the sender keeps these as the
+// baselines so both sides diff against the same values
+pub fn quantise(snapshot: &Snapshot) -> Snapshot {
+    let mut drones: Vec<DroneState> = snapshot.drones.iter()
+        .map(|drone| drone_state(drone.id, drone.is_player, &drone_fields(drone)))
+        .collect();
+    drones.sort_by_key(|drone| drone.id);
+
+    let mut beams: Vec<BeamState> = snapshot.beams.iter()
+        .filter_map(|beam| beam_state(beam.id, &beam_fields(beam)))
+        .collect();
+    beams.sort_by_key(|beam| beam.id);
+
+    Snapshot { tick: snapshot.tick, ack: snapshot.ack, drones, beams }
+}
+
+fn drone_fields(drone: &DroneState) -> [i32; DRONE_FIELDS] {
+    let mut fields = [0; DRONE_FIELDS];
+    let linear = drone.position.iter().chain(drone.velocity.iter()).chain(drone.spin.iter());
+
+    for (field, value) in fields[..9].iter_mut().zip(linear) {
+        *field = quantise_value(*value, POSITION_SCALE);
+    }
+
+    fields[9..13].copy_from_slice(&rotation_fields(drone.rotation));
+
+    let stats = [drone.health, drone.charge, drone.strike_charge, drone.shield, drone.boost];
+
+    for (field, stat) in fields[13..].iter_mut().zip(stats.iter()) {
+        *field = quantise_value(*stat, STAT_SCALE);
+    }
+
+    fields
+}
+
+fn drone_state(id: u32, is_player: bool, fields: &[i32]) -> DroneState {
+    let vector = |start: usize| [
+        dequantise(fields[start], POSITION_SCALE),
+        dequantise(fields[start + 1], POSITION_SCALE),
+        dequantise(fields[start + 2], POSITION_SCALE),
+    ];
+
+    DroneState {
+        id,
+        is_player,
+        position: vector(0),
+        velocity: vector(3),
+        spin: vector(6),
+        rotation: rotation(&fields[9..13]),
+        health: dequantise(fields[13], STAT_SCALE),
+        charge: dequantise(fields[14], STAT_SCALE),
+        strike_charge: dequantise(fields[15], STAT_SCALE),
+        shield: dequantise(fields[16], STAT_SCALE),
+        boost: dequantise(fields[17], STAT_SCALE),
+    }
+}
+
+fn beam_fields(beam: &BeamState) -> [i32; BEAM_FIELDS] {
+    let falloff = match beam.stats.falloff {
+        beam::Falloff::Linear        => 0,
+        beam::Falloff::InverseSquare => 1,
+        beam::Falloff::Constant      => 2,
+    };
+
+    [
+        quantise_value(beam.position[0], POSITION_SCALE),
+        quantise_value(beam.position[1], POSITION_SCALE),
+        quantise_value(beam.position[2], POSITION_SCALE),
+        quantise_value(beam.stats.gravity_radius, STAT_SCALE),
+        quantise_value(beam.stats.gravity_max_force, STAT_SCALE),
+        falloff,
+        quantise_value(beam.stats.radius_near, STAT_SCALE),
+        quantise_value(beam.stats.radius_medium, STAT_SCALE),
+        quantise_value(beam.stats.radius_far, STAT_SCALE),
+    ]
+}
+
+// None for an unknown falloff
+fn beam_state(id: u32, fields: &[i32]) -> Option<BeamState> {
+    let falloff = match fields[5] {
+        0 => beam::Falloff::Linear,
+        1 => beam::Falloff::InverseSquare,
+        2 => beam::Falloff::Constant,
+        _ => return None,
+    };
+
+    Some(BeamState {
+        id,
+        position: [
+            dequantise(fields[0], POSITION_SCALE),
+            dequantise(fields[1], POSITION_SCALE),
+            dequantise(fields[2], POSITION_SCALE),
+        ],
+        stats: beam::Stats {
+            gravity_radius: dequantise(fields[3], STAT_SCALE),
+            gravity_max_force: dequantise(fields[4], STAT_SCALE),
+            falloff,
+            radius_near: dequantise(fields[6], STAT_SCALE),
+            radius_medium: dequantise(fields[7], STAT_SCALE),
+            radius_far: dequantise(fields[8], STAT_SCALE),
+        },
+    })
+}
+
+// Out of range values saturate, NaN is zero
+fn quantise_value(value: f32, scale: f32) -> i32 {
+    (value * scale).round() as i32
+}
+
+fn dequantise(value: i32, scale: f32) -> f32 {
+    value as f32 / scale
+}
+
+// Index of the largest quaternion component and the other three, which are
+// at most 1/sqrt(2) in magnitude
+fn rotation_fields(q: [f32; 4]) -> [i32; 4] {
+    let largest = (1..4).fold(0, |largest, i| if q[i].abs() > q[largest].abs() { i } else { largest });
+    // q and -q are the same rotation, the largest component is kept positive
+    let sign = if q[largest] < 0.0 { -1.0 } else { 1.0 };
+
+    let mut fields = [largest as i32, 0, 0, 0];
+
+    for (field, i) in fields[1..].iter_mut().zip((0..4).filter(|i| *i != largest)) {
+        let value = (q[i] * sign / FRAC_1_SQRT_2).max(-1.0).min(1.0);
+        *field = quantise_value(value, ROTATION_SCALE);
+    }
+
+    fields
+}
+
+fn rotation(fields: &[i32]) -> [f32; 4] {
+    let largest = fields[0].rem_euclid(4) as usize;
+    let mut q = [0.0; 4];
+    let mut sum = 0.0;
+
+    for (field, i) in fields[1..4].iter().zip((0..4).filter(|i| *i != largest)) {
+        q[i] = dequantise(*field, ROTATION_SCALE).max(-1.0).min(1.0) * FRAC_1_SQRT_2;
+        sum = sum + q[i] * q[i];
+    }
+
+    q[largest] = (1.0f32 - sum).max(0.0).sqrt();
+
+    q
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn byte(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    // LEB128, 7 bits per byte
+    fn varint(&mut self, mut value: u32) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value = value >> 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    // zigzag keeps small negative values short
+    fn signed(&mut self, value: i32) {
+        self.varint(((value << 1) ^ (value >> 31)) as u32);
+    }
+
+    // Flags of the changed groups followed by their differences to `base`
+    fn fields(&mut self, fields: &[i32], base: &[i32], groups: &[(usize, usize)], mut flags: u8) {
+        for (bit, &(start, end)) in groups.iter().enumerate() {
+            if fields[start..end] != base[start..end] {
+                flags = flags | 1 << bit;
+            }
+        }
+
+        self.byte(flags);
+
+        for (bit, &(start, end)) in groups.iter().enumerate() {
+            if flags & 1 << bit == 0 {
+                continue;
+            }
+
+            for (field, base) in fields[start..end].iter().zip(base[start..end].iter()) {
+                self.signed(field.wrapping_sub(*base));
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.position)?;
+        self.position = self.position + 1;
+        Some(byte)
+    }
+
+    fn varint(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+
+            // the fifth byte only has 4 bits left
+            if shift == 28 && byte > 0x0f {
+                return None;
+            }
+
+            value = value | ((byte & 0x7f) as u32) << shift;
+
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+
+        None
+    }
+
+    fn signed(&mut self) -> Option<i32> {
+        let value = self.varint()?;
+        Some((value >> 1) as i32 ^ -((value & 1) as i32))
+    }
+
+    fn fields(&mut self, base: &[i32], groups: &[(usize, usize)]) -> Option<(u8, Vec<i32>)> {
+        let flags = self.byte()?;
+        let mut fields = base.to_vec();
+
+        for (bit, &(start, end)) in groups.iter().enumerate() {
+            if flags & 1 << bit == 0 {
+                continue;
+            }
+
+            for field in fields[start..end].iter_mut() {
+                *field = field.wrapping_add(self.signed()?);
+            }
+        }
+
+        Some((flags, fields))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    fn drone() -> impl Strategy<Value = DroneState> {
+        (
+            0u32..64,
+            any::<bool>(),
+            prop::array::uniform3(-500.0f32..500.0),
+            prop::array::uniform4(-1.0f32..1.0),
+            prop::array::uniform3(-50.0f32..50.0),
+            prop::array::uniform3(-10.0f32..10.0),
+            prop::array::uniform4(0.0f32..100.0),
+        ).prop_map(|(id, is_player, position, rotation, velocity, spin, stats)| {
+            let norm = rotation.iter().map(|q| q * q).sum::<f32>().sqrt();
+            let rotation = match norm > 1e-3 {
+                true  => [rotation[0] / norm, rotation[1] / norm, rotation[2] / norm, rotation[3] / norm],
+                false => [0.0, 0.0, 0.0, 1.0],
+            };
+
+            DroneState {
+                id,
+                is_player,
+                position,
+                rotation,
+                velocity,
+                spin,
+                health: stats[0],
+                charge: stats[1],
+                strike_charge: stats[2],
+                shield: stats[3] / 100.0,
+                boost: 0.0,
+            }
+        })
+    }
+
+    fn beam_state() -> impl Strategy<Value = BeamState> {
+        (
+            0u32..8,
+            prop::array::uniform3(-500.0f32..500.0),
+            prop_oneof![
+                Just(beam::Falloff::Linear),
+                Just(beam::Falloff::InverseSquare),
+                Just(beam::Falloff::Constant),
+            ],
+        ).prop_map(|(id, position, falloff)| BeamState {
+            id,
+            position,
+            stats: beam::Stats { falloff, ..Default::default() },
+        })
+    }
+
+    fn snapshot() -> impl Strategy<Value = Snapshot> {
+        (
+            1u32..1_000_000,
+            any::<u32>(),
+            prop::collection::vec(drone(), 0..16),
+            prop::collection::vec(beam_state(), 0..3),
+        ).prop_map(|(tick, ack, drones, beams)| Snapshot { tick, ack, drones, beams })
+    }
+
+    #[test]
+    fn unchanged_delta_is_small() {
+        let drone = DroneState {
+            id: 3,
+            is_player: true,
+            position: [120.5, -3.25, 40.0],
+            rotation: [0.0, 0.38268343, 0.0, 0.9238795],
+            velocity: [1.0, 0.0, -2.0],
+            spin: [0.0, 0.5, 0.0],
+            health: 80.0,
+            charge: 42.5,
+            strike_charge: 0.0,
+            shield: 0.25,
+            boost: 0.0,
+        };
+
+        let baseline = quantise(&Snapshot { tick: 3, ack: 0, drones: vec![drone.clone()], beams: Vec::new() });
+        let snapshot = Snapshot { tick: 6, ack: 2, drones: vec![drone], beams: Vec::new() };
+
+        let keyframe = encode(&snapshot, None);
+        let delta = encode(&snapshot, Some(&baseline));
+
+        // version, tick, ack, baseline, count, id, flags, count
+        assert_eq!(delta.len(), 8);
+        assert!(keyframe.len() > delta.len());
+
+        assert_eq!(decode(&delta, |tick| Some(&baseline).filter(|_| tick == 3)), Some(quantise(&snapshot)));
+        // the receiver doesn't have the baseline
+        assert_eq!(decode(&delta, |_| None), None);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut data = encode(&Snapshot { tick: 1, ack: 0, drones: Vec::new(), beams: Vec::new() }, None);
+
+        assert!(decode(&data, |_| None).is_some());
+
+        data[0] = VERSION + 1;
+        assert_eq!(decode(&data, |_| None), None);
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        let values = [0, 1, 127, 128, 16_383, 16_384, u32::MAX];
+        let signed = [0, -1, 1, -64, 64, i32::MIN, i32::MAX];
+
+        let mut writer = Writer::default();
+
+        for value in values.iter() {
+            writer.varint(*value);
+        }
+        for value in signed.iter() {
+            writer.signed(*value);
+        }
+
+        let mut reader = Reader { data: &writer.bytes, position: 0 };
+
+        for value in values.iter() {
+            assert_eq!(reader.varint(), Some(*value));
+        }
+        for value in signed.iter() {
+            assert_eq!(reader.signed(), Some(*value));
+        }
+
+        assert_eq!(reader.byte(), None);
+    }
+
+    proptest! {
+        #[test]
+        fn snapshots_round_trip(snapshot in snapshot(), baseline in snapshot()) {
+            let baseline = quantise(&Snapshot { tick: snapshot.tick - 1, ..baseline });
+            let expected = quantise(&snapshot);
+
+            let keyframe = encode(&snapshot, None);
+            prop_assert_eq!(decode(&keyframe, |_| None), Some(expected.clone()));
+
+            let delta = encode(&snapshot, Some(&baseline));
+            prop_assert_eq!(decode(&delta, |_| Some(&baseline)), Some(expected));
+        }
+
+        #[test]
+        fn quantisation_error_is_bounded(drone in drone()) {
+            let decoded = &quantise(&Snapshot {
+                tick: 1, ack: 0, drones: vec![drone.clone()], beams: Vec::new()
+            }).drones[0];
+
+            for i in 0..3 {
+                prop_assert!((decoded.position[i] - drone.position[i]).abs() <= 0.5 / POSITION_SCALE);
+                prop_assert!((decoded.velocity[i] - drone.velocity[i]).abs() <= 0.5 / POSITION_SCALE);
+            }
+
+            prop_assert!((decoded.health - drone.health).abs() <= 0.5 / STAT_SCALE);
+
+            // q and -q are the same rotation
+            let dot: f32 = decoded.rotation.iter().zip(drone.rotation.iter()).map(|(a, b)| a * b).sum();
+            prop_assert!(dot.abs() > 0.9999);
+        }
+
+        #[test]
+        fn decoder_survives_garbage(data in prop::collection::vec(any::<u8>(), 0..256)) {
+            let baseline = quantise(&Snapshot { tick: 1, ack: 0, drones: Vec::new(), beams: Vec::new() });
+
+            let _ = decode(&data, |_| None);
+            let _ = decode(&data, |_| Some(&baseline));
+        }
+
+        #[test]
+        fn decoder_survives_corruption(
+            snapshot in snapshot(),
+            index in any::<prop::sample::Index>(),
+            flip in 1u8..=255,
+            cut in any::<prop::sample::Index>(),
+        ) {
+            let data = encode(&snapshot, None);
+
+            let mut corrupted = data.clone();
+            let i = index.index(data.len());
+            corrupted[i] = corrupted[i] ^ flip;
+            let _ = decode(&corrupted, |_| None);
+
+            // a cut packet never passes for a whole one
+            let cut = &data[..cut.index(data.len())];
+            prop_assert_eq!(decode(cut, |_| None), None);
+        }
+    }
+}